@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use rand::prelude::*;
+
+use crate::app::{RandomWordGenerator, WordGenerator};
+use crate::keystats::{KeyStat, KeyStats};
+use crate::wordlist::{Sampling, WordList};
+
+/// Random words drawn per word in the test, the weakest of which is most
+/// likely to be picked
+const CANDIDATES: usize = 10;
+/// Keys pressed fewer times than this are too new to judge
+const MIN_PRESSES: u32 = 5;
+/// Chance a word with no weak letters still gets a look in, relative to
+/// how weak the others are
+const BASE_WEIGHT: f32 = 0.05;
+
+/// How much each letter, and each pair of letters, has been costing the
+/// user: its miss rate, plus how much slower than average it is to type
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Weakness {
+	letters:	HashMap<char, f32>,
+	bigrams:	HashMap<(char, char), f32>,
+}
+
+impl Weakness {
+	pub fn from(stats: &KeyStats) -> Self {
+		// word lists are lowercase, capitals come from punctuation
+		let mut letters: HashMap<char, KeyStat> = HashMap::new();
+		for (c, stat) in &stats.keys {
+			letters.entry(c.to_ascii_lowercase()).or_default().add(stat);
+		}
+		let mut bigrams: HashMap<(char, char), KeyStat> = HashMap::new();
+		for (bigram, stat) in &stats.bigrams {
+			let mut chars = bigram.chars().map(|c| c.to_ascii_lowercase());
+			if let (Some(first), Some(second)) = (chars.next(), chars.next()) {
+				bigrams.entry((first, second)).or_default().add(stat);
+			}
+		}
+
+		let (latency_ms, timed) = letters.values()
+			.fold((0, 0), |(ms, timed), stat| (ms + stat.latency_ms, timed + stat.timed));
+		let average_ms = (timed > 0).then(|| latency_ms as f32 / timed as f32);
+
+		Self {
+			letters: score_all(letters, average_ms),
+			bigrams: score_all(bigrams, average_ms),
+		}
+	}
+
+	/// Average weakness per letter of `word`, 0 if nothing in it is weak
+	pub fn score(&self, word: &str) -> f32 {
+		let chars: Vec<char> = word.chars().map(|c| c.to_ascii_lowercase()).collect();
+		if chars.is_empty() {
+			return 0.0;
+		}
+
+		let letters: f32 = chars.iter()
+			.filter_map(|c| self.letters.get(c))
+			.sum();
+		let bigrams: f32 = chars.windows(2)
+			.filter_map(|pair| self.bigrams.get(&(pair[0], pair[1])))
+			.sum();
+		(letters + bigrams) / chars.len() as f32
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.letters.is_empty() && self.bigrams.is_empty()
+	}
+}
+
+fn score_all<K: Eq + std::hash::Hash>(stats: HashMap<K, KeyStat>, average_ms: Option<f32>) -> HashMap<K, f32> {
+	stats.into_iter()
+		.filter(|(_, stat)| stat.presses() >= MIN_PRESSES)
+		.map(|(key, stat)| {
+			let slowness = match (stat.avg_latency_ms(), average_ms) {
+				(Some(ms), Some(average_ms)) if average_ms > 0.0 =>
+					(ms / average_ms - 1.0).max(0.0),
+				_ => 0.0,
+			};
+			(key, stat.miss_rate() + slowness)
+		})
+		.filter(|(_, score)| *score > 0.0)
+		.collect()
+}
+
+/// Draws words from a word list like `RandomWordGenerator`, but favours
+/// words full of the letters and pairs of letters the user misses most or
+/// types slowest. Without any key stats to go on, it's just random words.
+#[derive(Clone)]
+pub struct AdaptiveWordGenerator {
+	random:		RandomWordGenerator,
+	rng:		StdRng,
+	weakness:	Weakness,
+	words:		Vec<String>,
+}
+
+impl AdaptiveWordGenerator {
+	pub fn with_size(s: usize, word_list: &WordList, sampling: Sampling, stats: &KeyStats, seed: u64) -> Self {
+		let mut generator = Self {
+			random: RandomWordGenerator::unbounded(word_list, sampling, seed),
+			rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+			weakness: Weakness::from(stats),
+			words: Vec::with_capacity(s),
+		};
+		for _ in 0..s {
+			let word = generator.pick_word();
+			generator.words.push(word);
+		}
+		generator
+	}
+
+	fn pick_word(&mut self) -> String {
+		let candidates: Vec<String> = (0..CANDIDATES)
+			.map(|_| self.random.get_random_word_after(&self.words))
+			.collect();
+		if self.weakness.is_empty() {
+			return candidates[0].clone();
+		}
+
+		let weakness = &self.weakness;
+		candidates.choose_weighted(&mut self.rng, |word| BASE_WEIGHT + weakness.score(word))
+			.expect("expected a word to choose")
+			.clone()
+	}
+}
+
+impl WordGenerator for AdaptiveWordGenerator {
+	fn get_word_at(&mut self, index: usize) -> Option<String> {
+		self.get_word_at_frozen(index)
+	}
+	fn get_word_at_frozen(&self, index: usize) -> Option<String> {
+		self.words.get(index).cloned()
+	}
+	fn len(&self) -> Option<usize> {
+		Some(self.words.len())
+	}
+	fn repeat(&self) -> Box<dyn WordGenerator> {
+		Box::new(self.clone())
+	}
+}
+
+#[cfg(test)]
+mod adaptive_tests {
+	use super::*;
+	use crate::app::{KeyAction, Keystroke};
+
+	/// Stats from typing `text` correctly at `ms` per key, except for the
+	/// characters in `missed`
+	fn typed(text: &str, ms: u64, missed: &str) -> KeyStats {
+		let keystrokes: Vec<Keystroke> = text.chars().enumerate()
+			.map(|(i, c)| Keystroke {
+				time_ms: i as u64 * ms,
+				word: 0,
+				action: KeyAction::Char(c),
+				expected: Some(c),
+				correct: !missed.contains(c),
+			})
+			.collect();
+		KeyStats::from_keystrokes(&keystrokes)
+	}
+
+	#[test]
+	fn test_weakness() {
+		let mut stats = typed("asdfasdfasdfasdf", 100, "");
+		stats.record(&typed("qqqqqq", 100, "q")).unwrap();
+		let weakness = Weakness::from(&stats);
+
+		assert_eq!(weakness.score("sad"), 0.0);
+		assert!(weakness.score("qad") > 0.0);
+		assert!(weakness.score("qq") > weakness.score("qad"));
+		assert!(Weakness::from(&KeyStats::default()).is_empty());
+
+		// slow but accurate counts too
+		let mut stats = typed("asdfasdfasdfasdf", 100, "");
+		stats.record(&typed("zzzzzz", 400, "")).unwrap();
+		assert!(Weakness::from(&stats).score("za") > 0.0);
+	}
+
+	#[test]
+	fn test_adaptive_generator() {
+		let word_list = WordList {
+			name: "test".to_string(),
+			words: vec!["sad".to_string(), "qua".to_string()],
+			counts: None,
+		};
+		let stats = typed("ququququququ", 100, "q");
+
+		let generator = AdaptiveWordGenerator::with_size(50, &word_list, Sampling::default(), &stats, 7);
+		assert_eq!(generator.len(), Some(50));
+		let weak_words = (0..50)
+			.filter(|i| generator.get_word_at_frozen(*i).unwrap() == "qua")
+			.count();
+		assert!(weak_words > 35, "{}", weak_words);
+
+		let repeat = generator.repeat();
+		assert_eq!(repeat.get_word_at_frozen(49), generator.get_word_at_frozen(49));
+	}
+}
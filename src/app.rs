@@ -1,97 +1,151 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::time::{Duration, Instant};
-use std::fs;
+use rand::distr::weighted::WeightedIndex;
 use rand::prelude::*;
-
-static ENGLISH_1K: &str=include_str!("english-1k.txt");
+use serde::{Deserialize, Serialize};
+
+use crate::adaptive::AdaptiveWordGenerator;
+use crate::code::{self, CodeGenerator};
+use crate::config::{KeyBindings, ModeName};
+use crate::export::TestResult;
+use crate::ghost::{Ghost, PACES};
+use crate::history::{self, FilterItem, History, HistoryEntry, HistoryFilter, DATE_RANGES, SORT_ORDERS};
+use crate::keystats::KeyStats;
+use crate::modifiers::{ModifiedWordGenerator, Modifiers};
+use crate::quotes::{QuoteGenerator, QuoteLength};
+use crate::race::{Progress, Race, Start, COUNTDOWN};
+use crate::replay::Replay;
+use crate::settings::{cycle, cycle_number, MenuItem, Settings,
+	QUOTE_LENGTHS, TIME_LIMITS, WORD_COUNTS, ZEN_IDLE_LIMITS};
+use crate::share::TestCode;
+use crate::theme::{Theme, ThemeConfig, BUILTIN_THEMES};
+use crate::wordlist::{self, Sampling, WordList};
 
 pub trait WordGenerator {
 	fn get_word_at(&mut self, i: usize) -> Option<String>;
 	fn get_word_at_frozen(&self, i: usize) -> Option<String>;
 	fn len(&self) -> Option<usize>;
+	/// who the text is from, if it's attributed to anyone
+	fn source(&self) -> Option<String> {
+		None
+	}
+	/// whether a line break, rather than a space, follows word `i`
+	fn ends_line(&self, _i: usize) -> bool {
+		false
+	}
+	/// A new generator that serves the same words from the start again
+	fn repeat(&self) -> Box<dyn WordGenerator>;
 }
 
-//TODO timed mode is not implemented yet, so not setting size will break the game
-struct RandomWordGenerator {
+/// Draws that come up with a recent word before giving up and using it anyway
+const MAX_REDRAWS: usize = 100;
+
+/// A timeline bucket shorter than this is merged into the one before it
+const MIN_BUCKET_S: f64 = 0.5;
+
+/// Draws words from a word list. Without a size the generator never runs
+/// out and keeps producing words on demand (used by timed tests).
+#[derive(Clone)]
+pub struct RandomWordGenerator {
 	words: Vec<String>,
 	size: Option<usize>,
+	rng: StdRng,
 	word_list: Vec<String>,
+	/// None draws every word with the same chance
+	weights: Option<WeightedIndex<f64>>,
+	no_repeat: usize,
 }
 impl RandomWordGenerator {
-	pub fn with_size(s: usize) -> Self {
-		Self::from_wordlist_with_size(s, ENGLISH_1K)
+	pub fn with_size(s: usize, word_list: &WordList, sampling: Sampling, seed: u64) -> Self {
+		Self::from_wordlist(Some(s), word_list, sampling, seed)
+	}
+
+	pub fn unbounded(word_list: &WordList, sampling: Sampling, seed: u64) -> Self {
+		Self::from_wordlist(None, word_list, sampling, seed)
 	}
 
-	pub fn from_wordlist_with_size(s: usize, words: &str) -> Self {
+	fn from_wordlist(size: Option<usize>, word_list: &WordList, sampling: Sampling, seed: u64) -> Self {
+		let weights = word_list.weights(sampling.weighting);
+		// a short list can't avoid every recent word
+		let drawable = weights.as_ref()
+			.map_or(word_list.words.len(), |weights| weights.iter().filter(|w| **w > 0.0).count());
+
 		let mut generator = Self{ 
-			words: Vec::<String>::with_capacity(s), 
-			size: Some(s),
-			word_list: Self::load_word_list(words),
+			words: Vec::<String>::with_capacity(size.unwrap_or(0)), 
+			size,
+			rng: StdRng::seed_from_u64(seed),
+			word_list: word_list.words.clone(),
+			weights: weights.and_then(|weights| WeightedIndex::new(weights).ok()),
+			no_repeat: sampling.no_repeat.min(drawable.saturating_sub(1)),
 		};
-		for _ in 0..s {
-			generator.words.push(generator.get_random_word());
+		if let Some(s) = size {
+			generator.add_words(s);
 		}
 		generator
 	}
 
-	pub fn from_file_with_size(s: usize, filepath: &str) -> Self {
-		let word_str = fs::read_to_string(filepath)
-			.expect(
-				format!("Couldn't read word list file '{}'", filepath).as_str()
-			);
-		
-		Self::from_wordlist_with_size(s, word_str.as_str())
-	}
-
-	fn load_word_list(word_str: &str) -> Vec<String> {
-	//	let word_str = fs::read_to_string(filepath)
-	//		.expect(
-	//			format!("Could read word list file '{}'", filepath).as_str()
-	//		);
-		
-		let word_list: Vec<String> = word_str.split('\n')
-			.map(|s| s.to_string())
-			.collect();
-		
-		word_list
+	pub fn get_random_word(&mut self) -> String {
+		match &self.weights {
+			Some(weights) => self.word_list[weights.sample(&mut self.rng)].clone(),
+			None => self.word_list.choose(&mut self.rng)
+				.expect("expected to return a random word")
+				.to_string(),
+		}
 	}
 
-	fn get_random_word(&self) -> String {
-		let mut rng = rand::rng();
-		return self.word_list.choose(&mut rng)
-			.expect("expected to return a random word")
-			.to_string();
+	/// A random word that isn't one of the last `no_repeat` of `previous`
+	pub fn get_random_word_after(&mut self, previous: &[String]) -> String {
+		let recent = &previous[previous.len().saturating_sub(self.no_repeat)..];
+		for _ in 0..MAX_REDRAWS {
+			let word = self.get_random_word();
+			if !recent.contains(&word) {
+				return word;
+			}
+		}
+		self.get_random_word()
 	}
 
 	fn add_words(&mut self, n: usize) {
-		self.words.reserve(n);
+		let mut words = std::mem::take(&mut self.words);
+		words.reserve(n);
 		for _ in 0..n {
-			self.words.push(self.get_random_word());
+			let word = self.get_random_word_after(&words);
+			words.push(word);
 		}
+		self.words = words;
 	}
 }
 
 impl WordGenerator for RandomWordGenerator {	
 	fn get_word_at(&mut self, index: usize) -> Option<String> {
+		// a fixed length test has all its words drawn up front
+		if self.size.is_some_and(|size| index >= size) {
+			return None;
+		}
 		if index >= self.words.len() {
 			self.add_words(index - self.words.len()+1);
 		}
 
-		return Some(self.words[index].clone());
+		Some(self.words[index].clone())
 	}
 	fn get_word_at_frozen(&self, index: usize) -> Option<String> {
-		if index >= self.words.len() {return None;}
-		else {return Some(self.words[index].clone())}
+		self.words.get(index).cloned()
 	}
 	fn len(&self) -> Option<usize> {
-		match self.size {
-			Some(size) => return Some(size),
-			None => return None,
-		}
+		self.size
+	}
+	// words past the ones already drawn are picked at random again
+	fn repeat(&self) -> Box<dyn WordGenerator> {
+		Box::new(self.clone())
 	}
 }
 
+#[derive(Clone)]
 struct StaticWordGenerator {
 	words: Vec<String>,
+	/// indexes of the words a line break follows
+	line_ends: Vec<usize>,
 }
 impl StaticWordGenerator {
 	pub fn from(s: String) -> Self {
@@ -100,14 +154,18 @@ impl StaticWordGenerator {
 			.collect();
 		
 		Self {
-			words: words,
+			words,
+			line_ends: vec![],
 		}
 	}
 }
 impl WordGenerator for StaticWordGenerator {
+	fn ends_line(&self, index: usize) -> bool {
+		self.line_ends.contains(&index)
+	}
+
 	fn get_word_at_frozen(&self, index: usize) -> Option<String> {
-		if index >= self.words.len() {return None;}
-		else {return Some(self.words[index].clone())}
+		self.words.get(index).cloned()
 	}
 
 	fn get_word_at(&mut self, index: usize) -> Option<String> {
@@ -115,9 +173,91 @@ impl WordGenerator for StaticWordGenerator {
 	}
 	
 	fn len(&self) -> Option<usize> {
-		return Some(self.words.len());
+		Some(self.words.len())
+	}
+
+	fn repeat(&self) -> Box<dyn WordGenerator> {
+		Box::new(self.clone())
+	}
+}
+/// The configuration a test was taken in. Personal bests are only compared
+/// between tests with the exact same mode.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TestMode {
+	/// a fixed number of random words
+	Words(usize),
+	/// as many random words as fit in this many seconds
+	Time(u64),
+	/// user supplied text with this many words
+	Custom(usize),
+	/// a bundled quote from this length group
+	Quote(QuoteLength),
+	/// free typing with no target text
+	Zen,
+	/// this many random words picked to practise weak keys
+	Adaptive(usize),
+	/// source code from a file or a bundled snippet in this language
+	Code(String),
+}
+
+impl fmt::Display for TestMode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			TestMode::Words(n) => write!(f, "words {}", n),
+			TestMode::Time(s) => write!(f, "time {}s", s),
+			TestMode::Custom(n) => write!(f, "custom {}", n),
+			TestMode::Quote(length) => write!(f, "quote {}", length),
+			TestMode::Zen => write!(f, "zen"),
+			TestMode::Adaptive(n) => write!(f, "adaptive {}", n),
+			TestMode::Code(name) => write!(f, "code {}", name),
+		}
+	}
+}
+
+impl TestMode {
+	/// The kind of test, leaving out the length
+	pub fn name(&self) -> ModeName {
+		match self {
+			TestMode::Words(_) => ModeName::Words,
+			TestMode::Time(_) => ModeName::Time,
+			TestMode::Custom(_) => ModeName::Custom,
+			TestMode::Quote(_) => ModeName::Quote,
+			TestMode::Zen => ModeName::Zen,
+			TestMode::Adaptive(_) => ModeName::Adaptive,
+			TestMode::Code(_) => ModeName::Code,
+		}
 	}
 }
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyAction {
+	Char(char),
+	Space,
+	/// only typed in code tests
+	Enter,
+	Delete,
+}
+
+/// A single key press made during a session
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Keystroke {
+	/// milliseconds since the session started
+	pub time_ms:	u64,
+	/// index of the word the cursor was in
+	#[serde(default)]
+	pub word:		usize,
+	pub action:		KeyAction,
+	/// what the target text called for at the cursor (' ' at the end of a
+	/// word, '\n' at the end of a line of code), None for deletes, for
+	/// characters typed past a word's end and in zen mode
+	pub expected:	Option<char>,
+	/// always false for deletes, which don't count towards accuracy, and
+	/// always true for everything else in zen mode
+	pub correct:	bool,
+}
+
 #[derive(PartialEq, Debug)]
 enum SessionState {
 	Idle,
@@ -129,25 +269,108 @@ pub struct Session {
 	state: 				SessionState,
 	start_time: 		Option<Instant>,
 	duration:			Option<Duration>,
-	pub target_words:	Box<dyn WordGenerator>,
+	time_limit:			Option<Duration>,
+	/// zen sessions end after this long without a keystroke
+	idle_limit:			Option<Duration>,
+	/// code sessions start each line already indented
+	pub skip_indent:	bool,
+	pub mode:			TestMode,
+	/// name of the word list random words are drawn from
+	pub word_list:		Option<String>,
+	pub modifiers:		Modifiers,
+	pub sampling:		Sampling,
+	/// what everything random about the test was drawn with, None for tests
+	/// without anything random
+	pub seed:			Option<u64>,
+	/// None in zen mode, where there's nothing to copy
+	pub target_words:	Option<Box<dyn WordGenerator>>,
 	pub target_text:	Vec<String>,
 	pub input: 			Vec<String>,
+	pub keystrokes:		Vec<Keystroke>,
+	// running totals behind live_stats' accuracy, so it doesn't walk the
+	// whole log
+	keys_correct:		u32,
+	keys_typed:			u32,
+	// and behind its speed: characters in the input, and those of them in
+	// finished words that were typed right
+	chars_correct:		u32,
+	chars_typed:		u32,
 }
 
 impl Session {
 	pub fn default() -> Self {
 		//Self::from("The quick brown fox jumps over the lazy dog".to_string())
-		Self::random_with_size(25)
+		Self::random_with_size(25, &WordList::default(), Sampling::default(), rand::random())
+	}
+
+	/// An idle session of `mode` over `target_words`, that the other
+	/// constructors build on
+	fn new(mode: TestMode, target_words: Option<Box<dyn WordGenerator>>, target_text: Vec<String>) -> Self {
+		Self {
+			state: 			SessionState::Idle,
+			start_time:		None,
+			duration:		None,
+			time_limit:		None,
+			idle_limit:		None,
+			skip_indent:	false,
+			mode,
+			word_list:		None,
+			modifiers:		Modifiers::default(),
+			sampling:		Sampling::default(),
+			seed:			None,
+			target_words,
+			target_text,
+			input:			vec!["".to_string()],
+			keystrokes:		vec![],
+			keys_correct:	0,
+			keys_typed:		0,
+			chars_correct:	0,
+			chars_typed:	0,
+		}
+	}
+
+	pub fn random_with_size(s: usize, word_list: &WordList, sampling: Sampling, seed: u64) -> Self {
+		let words = RandomWordGenerator::with_size(s, word_list, sampling, seed);
+		Self {
+			word_list: Some(word_list.name.clone()),
+			sampling,
+			seed: Some(seed),
+			..Self::new(TestMode::Words(s), Some(Box::from(words)), vec![])
+		}
+	}
+
+	/// A test that ends once `limit` has passed since the first keystroke
+	pub fn timed(limit: Duration, word_list: &WordList, sampling: Sampling, seed: u64) -> Self {
+		let words = RandomWordGenerator::unbounded(word_list, sampling, seed);
+		Self {
+			time_limit: Some(limit),
+			word_list: Some(word_list.name.clone()),
+			sampling,
+			seed: Some(seed),
+			..Self::new(TestMode::Time(limit.as_secs()), Some(Box::from(words)), vec![])
+		}
+	}
+
+	/// Random words that lean towards the keys `stats` say are weakest
+	pub fn adaptive(s: usize, word_list: &WordList, sampling: Sampling, stats: &KeyStats, seed: u64) -> Self {
+		let words = AdaptiveWordGenerator::with_size(s, word_list, sampling, stats, seed);
+		Self {
+			word_list: Some(word_list.name.clone()),
+			sampling,
+			seed: Some(seed),
+			..Self::new(TestMode::Adaptive(s), Some(Box::from(words)), vec![])
+		}
 	}
 
-	pub fn random_with_size(s: usize) -> Self {
+	pub fn quote(length: QuoteLength, seed: u64) -> Self {
+		let generator = QuoteGenerator::random(Some(length), &mut StdRng::seed_from_u64(seed));
+		let target_text: Vec<String> = (0..generator.len().unwrap_or(0))
+			.filter_map(|i| generator.get_word_at_frozen(i))
+			.collect();
+
 		Self {
-			state: SessionState::Idle,
-			start_time: None,
-			duration: None,
-			target_words: Box::from(RandomWordGenerator::with_size(s)),
-			target_text: vec![],
-			input: vec!["".to_string()],
+			seed: Some(seed),
+			..Self::new(TestMode::Quote(length), Some(Box::from(generator)), target_text)
 		}
 	}
 
@@ -156,14 +379,99 @@ impl Session {
 			.map(|s| s.to_string())
 			.collect();
 		
+		Self::new(TestMode::Custom(target_text.len()), Some(Box::from(StaticWordGenerator::from(s))), target_text)
+	}
+
+	/// Source code, typed with its line breaks. With `skip_indent` each
+	/// line starts out indented, so only the code itself is typed.
+	pub fn code(generator: CodeGenerator, skip_indent: bool, seed: Option<u64>) -> Self {
+		let target_text: Vec<String> = (0..generator.len().unwrap_or(0))
+			.filter_map(|i| generator.get_word_at_frozen(i))
+			.collect();
+		let first_indent = match skip_indent {
+			true => code::indentation(&target_text[0]).to_string(),
+			false => String::new(),
+		};
+		let mode = TestMode::Code(generator.name.clone());
+
 		Self {
-			state: 			SessionState::Idle,
-			start_time:		None,
-			duration:		None,
-			target_words:	Box::from(StaticWordGenerator::from(s)),
-			target_text: 	target_text,
-			input:			vec!["".to_string()],
+			skip_indent,
+			seed,
+			input: vec![first_indent],
+			..Self::new(mode, Some(Box::from(generator)), target_text)
+		}
+	}
+
+	/// Free typing that ends on request, or after `idle_limit` without a
+	/// keystroke
+	pub fn zen(idle_limit: Option<Duration>) -> Self {
+		Self {
+			idle_limit,
+			..Self::new(TestMode::Zen, None, vec![])
+		}
+	}
+
+	/// A session over the words a saved test showed, for its keystrokes to
+	/// be played back into. It has no time limit, since the keystrokes
+	/// stop where the test did.
+	pub fn replay(entry: &HistoryEntry) -> Self {
+		let target_words: Option<Box<dyn WordGenerator>> = match entry.mode {
+			TestMode::Zen => None,
+			_ => Some(Box::new(StaticWordGenerator {
+				words: entry.words.clone(),
+				line_ends: entry.line_ends.clone(),
+			})),
+		};
+		// only tests with a fixed text know it all up front
+		let target_text = match entry.mode {
+			TestMode::Custom(_) | TestMode::Quote(_) | TestMode::Code(_) => entry.words.clone(),
+			_ => vec![],
+		};
+		let first_indent = match (entry.skip_indent, entry.words.first()) {
+			(true, Some(word)) => code::indentation(word).to_string(),
+			_ => String::new(),
+		};
+
+		Self {
+			skip_indent: entry.skip_indent,
+			word_list: entry.word_list.clone(),
+			modifiers: Modifiers {
+				punctuation: entry.punctuation,
+				numbers: entry.numbers,
+				..Modifiers::default()
+			},
+			input: vec![first_indent],
+			..Self::new(entry.mode.clone(), target_words, target_text)
+		}
+	}
+
+	/// A fresh session over exactly the same words as this one
+	pub fn repeat(&self) -> Self {
+		let target_words = self.target_words.as_ref().map(|words| words.repeat());
+		Self {
+			time_limit: self.time_limit,
+			idle_limit: self.idle_limit,
+			skip_indent: self.skip_indent,
+			word_list: self.word_list.clone(),
+			modifiers: self.modifiers,
+			sampling: self.sampling,
+			seed: self.seed,
+			input: vec![self.input_start(0)],
+			..Self::new(self.mode.clone(), target_words, self.target_text.clone())
+		}
+	}
+
+	/// Adds punctuation and/or numbers to the target words
+	pub fn with_modifiers(mut self, modifiers: Modifiers) -> Self {
+		if modifiers.is_active() {
+			// a different stream from the one the words came from
+			let seed = self.seed.unwrap_or_else(rand::random).wrapping_add(1);
+			self.target_words = self.target_words.map(|words| -> Box<dyn WordGenerator> {
+				Box::new(ModifiedWordGenerator::new(words, modifiers, seed))
+			});
 		}
+		self.modifiers = modifiers;
+		self
 	}
 
 	pub fn start_session(&mut self) {
@@ -184,94 +492,360 @@ impl Session {
 		self.state = SessionState::Finished;
 	}
 
+	/// Ends a timed session whose deadline has passed. Returns true if the
+	/// session is over, in which case any pending input should be dropped.
+	fn check_time_limit(&mut self) -> bool {
+		if self.state != SessionState::Active {
+			return self.state == SessionState::Finished;
+		}
+
+		let (Some(limit), Some(start)) = (self.time_limit, self.start_time) else {
+			return false;
+		};
+
+		if start.elapsed() < limit {
+			return false;
+		}
+
+		// keystrokes landing between the deadline and the next tick don't count
+		self.duration = Some(limit);
+		self.state = SessionState::Finished;
+		true
+	}
+
+	/// Ends a zen session, not counting any time after the last keystroke
+	pub fn finish(&mut self) {
+		if !self.is_zen() || self.state != SessionState::Active {
+			return;
+		}
+
+		// a trailing space doesn't start another word
+		if self.input.len() > 1 && self.input.last().is_some_and(|word| word.is_empty()) {
+			self.input.pop();
+		}
+
+		let last_key_ms = self.keystrokes.last().map_or(0, |k| k.time_ms);
+		self.duration = Some(Duration::from_millis(last_key_ms.max(1)));
+		self.state = SessionState::Finished;
+	}
+
+	fn check_idle_limit(&mut self) {
+		let (Some(limit), Some(start)) = (self.idle_limit, self.start_time) else {
+			return;
+		};
+
+		let last_key = Duration::from_millis(self.keystrokes.last().map_or(0, |k| k.time_ms));
+		if self.state == SessionState::Active && start.elapsed() >= last_key + limit {
+			self.finish();
+		}
+	}
+
+	/// Called on every refresh so timed and zen sessions end even without
+	/// input
+	pub fn on_tick(&mut self) {
+		self.check_time_limit();
+		self.check_idle_limit();
+	}
+
+	pub fn is_zen(&self) -> bool {
+		self.target_words.is_none()
+	}
+
+	pub fn is_code(&self) -> bool {
+		matches!(self.mode, TestMode::Code(_))
+	}
+
+	/// Every target word generated so far, which is at least as far as the
+	/// typing screen has shown
+	pub fn shown_words(&self) -> Vec<String> {
+		let Some(words) = &self.target_words else {
+			return vec![];
+		};
+		(0..).map_while(|i| words.get_word_at_frozen(i)).collect()
+	}
+
+	/// Types one keystroke from a saved test's log
+	pub fn play(&mut self, action: KeyAction) {
+		match action {
+			KeyAction::Char(c) => self.on_char(c),
+			KeyAction::Space => self.on_space(),
+			KeyAction::Enter => self.on_enter(),
+			KeyAction::Delete => self.on_del(),
+		}
+	}
+
+	fn target_word(&mut self, index: usize) -> Option<String> {
+		self.target_words.as_mut()?.get_word_at(index)
+	}
+
+	/// Whether a line break follows target word `index`
+	pub fn ends_line(&self, index: usize) -> bool {
+		self.target_words.as_ref().is_some_and(|words| words.ends_line(index))
+	}
+
+	/// What input word `index` holds before anything is typed into it: its
+	/// indentation, when that's skipped
+	fn input_start(&self, index: usize) -> String {
+		if !self.skip_indent {
+			return String::new();
+		}
+		self.target_text.get(index)
+			.map_or(String::new(), |word| code::indentation(word).to_string())
+	}
+
+	/// Whether target word `index` is the one a fixed length test ends on
+	fn is_last_word(&self, index: usize) -> bool {
+		self.target_words.as_ref().and_then(|words| words.len()) == Some(index + 1)
+	}
+
+	/// Whether nothing has been typed into the word at the cursor yet
+	fn is_cursor_word_blank(&self) -> bool {
+		let cursor_word = self.get_cursor_word();
+		self.input[cursor_word] == self.input_start(cursor_word)
+	}
+
 	pub fn on_char(&mut self, c: char) {
 		if self.state == SessionState::Idle {
 			self.start_session();
 		}
 
+		if self.check_time_limit() {
+			return;
+		}
+
 		assert_eq!(self.state, SessionState::Active, 
 				"Input received before session started!");
 		
 		let input_len = self.input.len();
+		let target_word = self.target_word(input_len-1)
+				.unwrap_or_default();
+		let typed_len = self.get_cursor_char();
+		let expected = target_word.chars().nth(typed_len);
+		let correct = self.is_zen() || expected == Some(c);
+		self.record_keystroke(KeyAction::Char(c), expected, correct);
+
 		let last_word = self.input.last_mut()
 				.expect("No words in input!");
 
 		last_word.push(c);
+		self.chars_typed += 1;
 
 		// check to end the session
-		if self.target_words.len() == Some(input_len) {
-			let last_target_word = self.target_words.get_word_at(input_len-1)
-									.expect("empty target text!");
-			
-			if last_target_word == *last_word {
-				self.stop_session();
-			}
+		if target_word == *last_word && self.is_last_word(input_len-1) {
+			self.stop_session();
 		}
 	}
 
 	pub fn on_space(&mut self) {
-		if self.state == SessionState::Idle {
+		let input_len = self.input.len();
+		let target_word = self.target_word(input_len-1)
+				.unwrap_or_default();
+		let typed_len = self.get_cursor_char();
+
+		// spaces within a word, like indentation, are typed like any other
+		// character
+		if target_word.chars().nth(typed_len) == Some(' ') {
+			self.on_char(' ');
+			return;
+		}
+
+		if self.state == SessionState::Idle || self.check_time_limit() {
 			return;
 		}
 
 		assert_eq!(self.state, SessionState::Active, 
 				"Input received before session started!");
+
+		// ignore spaces if the last word is already empty
+		if self.is_cursor_word_blank() && !self.is_last_word(input_len-1) {
+			return;
+		}
+
+		// a space only counts as correct if it ends a correctly typed word
+		let (expected, correct) = if self.is_zen() {
+			(None, true)
+		} else {
+			let word_end = if self.ends_line(input_len-1) { '\n' } else { ' ' };
+			let expected = target_word.chars().nth(typed_len).unwrap_or(word_end);
+			(Some(expected), self.input[input_len-1] == target_word && word_end == ' ')
+		};
+		self.record_keystroke(KeyAction::Space, expected, correct);
+		self.finish_word();
 		
-		let input_len = self.input.len();
-		let last_word = self.input.last()
-				.expect("No words in input!");
-		
-		if self.target_text.len() == input_len {
+		if self.is_last_word(input_len-1) {
 			self.stop_session();
 			return;		
 		}
 
-		// ignore spaces if the last word is already empty
-		if last_word != "" {
-			self.input.push("".to_string());
+		self.input.push(self.input_start(input_len));
+	}
+
+	/// Enter ends a line of code, and is a mistake anywhere else, just like
+	/// a space at the end of a line
+	pub fn on_enter(&mut self) {
+		if !self.is_code() || self.state == SessionState::Idle || self.check_time_limit() {
+			return;
+		}
+
+		assert_eq!(self.state, SessionState::Active, 
+				"Input received before session started!");
+
+		let input_len = self.input.len();
+		let target_word = self.target_word(input_len-1)
+				.unwrap_or_default();
+
+		// blank lines are empty words, so Enter is all they need
+		if self.is_cursor_word_blank() && !target_word.is_empty() {
+			return;
+		}
+
+		// either ends the last word
+		let is_last = self.is_last_word(input_len-1);
+		let word_end = if self.ends_line(input_len-1) || is_last { '\n' } else { ' ' };
+		let expected = target_word.chars().nth(self.get_cursor_char()).unwrap_or(word_end);
+		let correct = self.input[input_len-1] == target_word && word_end == '\n';
+		self.record_keystroke(KeyAction::Enter, Some(expected), correct);
+		self.finish_word();
+
+		if is_last {
+			self.stop_session();
+			return;
 		}
+
+		self.input.push(self.input_start(input_len));
 	}
 
 	pub fn on_del(&mut self) {
+		if self.state == SessionState::Idle || self.check_time_limit() {
+			return;
+		}
+
 		assert_eq!(self.state, SessionState::Active, 
 				"Input received before session started!");
 		
+		let is_blank = self.is_cursor_word_blank();
+		if self.input.len() == 1 && is_blank {
+			return; // nothing to delete
+		}
+		self.record_keystroke(KeyAction::Delete, None, false);
+
 		let last_word = self.input.last_mut()
 				.expect("No words in input!");
 		
-		if last_word == "" {
-			if self.input.len() != 1 {
-				self.input.pop(); // remove last word
-			}
+		if is_blank {
+			self.input.pop(); // remove last word
+			// the word before is back at the cursor, without its space
+			let (typed, correct) = self.finished_chars(self.get_cursor_word());
+			self.chars_typed -= 1;
+			self.chars_correct -= if correct { typed } else { 0 };
 		} else {
 			last_word.pop(); // remove last char
+			self.chars_typed -= 1;
+		}
+	}
+
+	/// Characters input word `index` adds to the speed once finished,
+	/// counting the space after it, and whether they were typed right
+	fn finished_chars(&self, index: usize) -> (u32, bool) {
+		let word = &self.input[index];
+		let typed = word.chars().count().saturating_sub(self.input_start(index).chars().count()) + 1;
+		let correct = self.is_zen() || self.target_words.as_ref()
+			.and_then(|words| words.get_word_at_frozen(index))
+			.is_some_and(|target| *word == target);
+		(typed as u32, correct)
+	}
+
+	fn finish_word(&mut self) {
+		let (typed, correct) = self.finished_chars(self.get_cursor_word());
+		self.chars_typed += 1;
+		self.chars_correct += if correct { typed } else { 0 };
+	}
+
+	fn record_keystroke(&mut self, action: KeyAction, expected: Option<char>, correct: bool) {
+		let time_ms = self.start_time
+			.map_or(0, |start| start.elapsed().as_millis() as u64);
+		let word = self.get_cursor_word();
+
+		self.keystrokes.push(Keystroke { time_ms, word, action, expected, correct });
+		if action != KeyAction::Delete {
+			self.keys_typed += 1;
+			self.keys_correct += correct as u32;
+		}
+	}
+
+	/// Rough stats for the session so far. They come from running totals
+	/// plus a look at the word at the cursor, so calling this every frame
+	/// stays cheap however long the test gets.
+	pub fn live_stats(&self) -> LiveStats {
+		// the first keystrokes would otherwise show absurd speeds
+		let minutes = self.get_age_s().unwrap_or(0.0).max(1.0) / 60.0;
+		let per_minute = |chars: u32| chars as f32 / 5.0 / minutes as f32;
+
+		// like the final stats, speed goes by what's left in the input, so
+		// keys that were deleted again don't count towards it. The word at
+		// the cursor is the only one left to check, and it's fine as long as
+		// it's on track.
+		let i = self.get_cursor_word();
+		let word = &self.input[i];
+		let on_track = self.is_zen() || self.target_words.as_ref()
+			.and_then(|words| words.get_word_at_frozen(i))
+			.is_some_and(|target| target.starts_with(word.as_str()));
+		let typed = word.chars().count().saturating_sub(self.input_start(i).chars().count()) as u32;
+		let chars_correct = self.chars_correct + if on_track { typed } else { 0 };
+
+		let acc = if self.keys_typed > 0 {
+			self.keys_correct as f32 / self.keys_typed as f32
+		} else {
+			1.0
+		};
+
+		LiveStats {
+			wpm: per_minute(chars_correct),
+			wpm_raw: per_minute(self.chars_typed),
+			acc,
+			words_done: self.get_cursor_word(),
+			words_total: self.target_words.as_ref().and_then(|words| words.len()),
 		}
 	}
 
 	pub fn get_age_s(&self) -> Option<f64> {
 		if self.state == SessionState::Idle { return None };
 
-		match self.start_time {
-			Some(start) => return Some(start.elapsed().as_secs_f64()),
-			None		=> return None,
-		}
+		self.start_time.map(|start| start.elapsed().as_secs_f64())
+	}
+
+	/// Seconds left in a timed session, or None for untimed sessions
+	pub fn get_time_left_s(&self) -> Option<f64> {
+		let limit = self.time_limit?;
+		let age = match self.state {
+			SessionState::Idle => 0.0,
+			SessionState::Active => self.get_age_s().unwrap_or(0.0),
+			SessionState::Finished => limit.as_secs_f64(),
+		};
+
+		Some((limit.as_secs_f64() - age).max(0.0))
+	}
+
+	pub fn is_timed(&self) -> bool {
+		self.time_limit.is_some()
 	}
 
 	pub fn get_final_duration_s(&self) -> Option<f64> {
-		match self.duration {
-			Some(dur) => return Some(dur.as_secs_f64()),
-			None => return None,
-		}
+		self.duration.map(|dur| dur.as_secs_f64())
 	}
 
 	pub fn get_input_words(&self) -> Vec<String> {
 		self.input.clone()
 	}
 
+	/// The target words the input is up to. In zen mode the input is its
+	/// own target.
 	pub fn get_attempted_words(&self) -> Vec<String> {
+		let Some(target) = &self.target_words else {
+			return self.input.clone();
+		};
 		let l = self.input.len();
 		let mut words = Vec::with_capacity(l);
-		let target = &self.target_words;
 
 		for i in 0..l{
 			match target.get_word_at_frozen(i) {
@@ -284,25 +858,60 @@ impl Session {
 	}
 
 	pub fn get_cursor_word(&self) -> usize {
-		return self.input.len()-1;
+		self.input.len()-1
 	}
 
+	/// How many characters into its word the cursor is
 	pub fn get_cursor_char(&self) -> usize {
 		let last_word = self.input.last().expect("no words in input!");
-		return last_word.len();
+		last_word.chars().count()
 	}
 }
 
-#[derive(Default)]
-pub struct SessionStats {
+/// What the typing screen shows while a session is running
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiveStats {
 	pub wpm:			f32,
 	pub wpm_raw:		f32,
 	pub acc:			f32,
+	/// words finished so far
+	pub words_done:		usize,
+	/// None for tests without a fixed length
+	pub words_total:	Option<usize>,
+}
+
+/// Speed over one second of a session, used for the result graph
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct WpmSample {
+	/// end of this sample, in seconds since the session started
+	pub time_s:		f64,
+	/// wpm from the start of the session up to `time_s`
+	pub wpm:		f32,
+	/// raw wpm within this second alone
+	pub wpm_raw:	f32,
+	pub errors:		u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct SessionStats {
+	pub wpm:			f32,
+	pub wpm_raw:		f32,
+	/// None in zen mode, where there's nothing to be accurate to
+	pub acc:			Option<f32>,
 	pub char_corr:		i32,
 	pub char_total:		i32,
 	pub word_corr:		i32,
 	pub word_total:		i32,
 	pub duration_s:		f64,
+	pub timeline:		Vec<WpmSample>,
+	/// 1.0 when raw wpm never changed between seconds, towards 0.0 the
+	/// more it varied
+	pub consistency:	f32,
+	/// wpm of the fastest single word
+	pub burst_max:		f32,
+	/// mean wpm across single words
+	pub burst_avg:		f32,
 }
 
 impl SessionStats {
@@ -313,22 +922,43 @@ impl SessionStats {
 		
 		// Calculate char_total, char_corr, word_total, word_corr
 
-		let mut char_total = 0 as i32;
-		let mut char_corr = 0 as i32;
+		let mut char_total = 0;
+		let mut char_corr = 0;
 		
-		let mut word_total = 0 as i32;
-		let mut word_corr = 0 as i32;
+		let mut word_total = 0;
+		let mut word_corr = 0;
 		
 		// used to calculate wpm
 		let mut correct_word_char_count = 0;
 
 		let input_words = session.get_input_words();
 		let attempted_words = session.get_attempted_words();
+		let last_i = input_words.len() - 1;
+
+		for (i, in_word) in input_words.iter().enumerate() {
+			let mut in_word = in_word.as_str();
+			let mut att_word = attempted_words[i].as_str();
+
+			// skipped indentation wasn't typed, so it isn't scored
+			let skipped = session.input_start(i);
+			if !skipped.is_empty() && in_word.starts_with(&skipped) {
+				in_word = &in_word[skipped.len()..];
+				att_word = &att_word[skipped.len()..];
+			}
+
+			// a timed test can cut the last word short, so only the typed
+			// part of it is scored
+			if session.is_timed() && i == last_i {
+				if in_word.is_empty() {
+					break;
+				}
+				let typed_len = att_word.char_indices()
+					.nth(in_word.chars().count())
+					.map_or(att_word.len(), |(idx, _)| idx);
+				att_word = &att_word[..typed_len];
+			}
 
-		let mut i = 0;
-		for in_word in input_words {
-			let att_word = &attempted_words[i];
-			let (corr, ttl, is_correct) = Self::word_compare(&in_word.as_str(), &att_word.as_str());
+			let (corr, ttl, is_correct) = Self::word_compare(in_word, att_word);
 			char_corr += corr;
 			char_total += ttl;
 			if is_correct {
@@ -336,7 +966,6 @@ impl SessionStats {
 				correct_word_char_count += ttl;
 			}
 			word_total += 1;
-			i += 1;
 		}
 		
 		// account for spaces
@@ -347,7 +976,7 @@ impl SessionStats {
 		
 		let duration_s = session.get_final_duration_s()
 			.expect("Calculating stats on a session without duration");
-		let duration_min: f64 = duration_s / (60 as f64);
+		let duration_min: f64 = duration_s / 60.0;
 		
 		// let wpm = (word_corr as f32) / (duration_min as f32);
 		// let wpm_raw = (word_total as f32) / (duration_min as f32);
@@ -355,15 +984,136 @@ impl SessionStats {
 		let wpm = (correct_word_char_count as f32) / (5.0 * duration_min as f32);
 		let wpm_raw = (char_total as f32) / (5.0 * duration_min as f32);
 
-		let acc = (char_corr as f32) / (char_total as f32);
+		// like MonkeyType, accuracy is over every key pressed, so mistakes
+		// that were corrected afterwards still count against it
+		let (keys_corr, keys_total) = session.keystrokes.iter()
+			.filter(|k| k.action != KeyAction::Delete)
+			.fold((0, 0), |(corr, ttl), k| (corr + k.correct as i32, ttl + 1));
+
+		let acc = if session.is_zen() {
+			None
+		} else if keys_total > 0 {
+			Some((keys_corr as f32) / (keys_total as f32))
+		} else {
+			Some((char_corr as f32) / (char_total as f32))
+		};
 		
+		let timeline = Self::timeline(&session.keystrokes, duration_s);
+		let consistency = Self::consistency(Self::whole_seconds(&timeline));
+		let bursts = Self::word_bursts(&session.keystrokes, &session.input);
+		let burst_max = bursts.iter().copied().fold(0.0, f32::max);
+		let burst_avg = if bursts.is_empty() {
+			0.0
+		} else {
+			bursts.iter().sum::<f32>() / bursts.len() as f32
+		};
+
 		Self {
-			wpm: wpm, wpm_raw: wpm_raw, acc: acc, char_corr: char_corr,
-			char_total: char_total, word_corr: word_corr, word_total: word_total,
-			duration_s: duration_s
+			wpm, wpm_raw, acc, char_corr,
+			char_total, word_corr, word_total,
+			duration_s, timeline, consistency,
+			burst_max, burst_avg,
 		}		
 	}
 
+	/// MonkeyType's consistency: the coefficient of variation of raw wpm
+	/// per second, squashed into 0..1 so that higher is steadier
+	fn consistency(timeline: &[WpmSample]) -> f32 {
+		if timeline.is_empty() {
+			return 0.0;
+		}
+
+		let n = timeline.len() as f32;
+		let mean = timeline.iter().map(|s| s.wpm_raw).sum::<f32>() / n;
+		if mean == 0.0 {
+			return 0.0;
+		}
+
+		let variance = timeline.iter()
+			.map(|s| (s.wpm_raw - mean).powi(2))
+			.sum::<f32>() / n;
+		let cov = variance.sqrt() / mean;
+
+		1.0 - (cov + cov.powi(3) / 3.0 + cov.powi(5) / 5.0).tanh()
+	}
+
+	/// The timeline without a last bucket that's shorter than a second,
+	/// which is too few keys to say much about how steady the typing was
+	fn whole_seconds(timeline: &[WpmSample]) -> &[WpmSample] {
+		match timeline {
+			[.., prev, last] if last.time_s - prev.time_s < 1.0 => &timeline[..timeline.len() - 1],
+			_ => timeline,
+		}
+	}
+
+	/// Speed of every finished word, measured from the keystroke that ended
+	/// the previous word (or the start of the session) to its own last one
+	fn word_bursts(keystrokes: &[Keystroke], input: &[String]) -> Vec<f32> {
+		let mut bursts = vec![];
+		let mut word_start_ms = 0;
+		
+		for (i, word) in input.iter().enumerate() {
+			let last_key = keystrokes.iter()
+				.rfind(|k| k.word == i && k.action != KeyAction::Delete);
+			let Some(last_key) = last_key else {
+				break;
+			};
+
+			let elapsed_ms = last_key.time_ms.saturating_sub(word_start_ms);
+			if !word.is_empty() && elapsed_ms > 0 {
+				let minutes = elapsed_ms as f32 / 60_000.0;
+				bursts.push(word.chars().count() as f32 / 5.0 / minutes);
+			}
+			word_start_ms = last_key.time_ms;
+		}
+
+		bursts
+	}
+
+	/// Splits the keystroke log into one second buckets. The last bucket
+	/// may be shorter than a second, or up to half a second longer, since a
+	/// sliver of a second at the end is folded into the one before it.
+	fn timeline(keystrokes: &[Keystroke], duration_s: f64) -> Vec<WpmSample> {
+		// a handful of keys over a few milliseconds would spike raw wpm
+		let seconds = if duration_s >= 1.0 && duration_s.fract() < MIN_BUCKET_S {
+			duration_s.floor() as usize
+		} else {
+			duration_s.ceil() as usize
+		};
+		let mut timeline = Vec::with_capacity(seconds);
+
+		let mut keys = keystrokes.iter()
+			.filter(|k| k.action != KeyAction::Delete)
+			.peekable();
+		let mut correct_so_far = 0;
+
+		for sec in 1..=seconds {
+			let end_s = if sec == seconds { duration_s } else { sec as f64 };
+			let bucket_s = end_s - (sec - 1) as f64;
+
+			let mut typed = 0;
+			let mut errors = 0;
+			while let Some(k) = keys.next_if(|k| (k.time_ms as f64) < end_s * 1000.0
+					|| sec == seconds) {
+				typed += 1;
+				if k.correct {
+					correct_so_far += 1;
+				} else {
+					errors += 1;
+				}
+			}
+
+			timeline.push(WpmSample {
+				time_s: end_s,
+				wpm: (correct_so_far as f32) / 5.0 / (end_s / 60.0) as f32,
+				wpm_raw: (typed as f32) / 5.0 / (bucket_s / 60.0) as f32,
+				errors,
+			});
+		}
+
+		timeline
+	}
+
 	// Returns: (correct chars, total chars, word correct)
 	fn word_compare(inp: &str, targ: &str) -> (i32, i32, bool) {
 		let mut char_corr = 0;
@@ -371,152 +1121,656 @@ impl SessionStats {
 		let mut inp_chars = inp.chars();
 		let mut targ_chars = targ.chars();
 
-		loop {
-			match (inp_chars.next(), targ_chars.next()) {
-				(Some(inp_ch), Some(targ_ch)) => {
-					if inp_ch == targ_ch {
-						char_corr += 1;
-					}
-				},
-				_ => break
+		while let (Some(inp_ch), Some(targ_ch)) = (inp_chars.next(), targ_chars.next()) {
+			if inp_ch == targ_ch {
+				char_corr += 1;
 			}
 		}
 
-		let ttl_chars: i32;
-		if targ.len() > inp.len() {
-			ttl_chars = targ.len() as i32;
-		} else {
-			ttl_chars = inp.len() as i32;
-		}
+		let ttl_chars = inp.chars().count().max(targ.chars().count()) as i32;
 	
-		return(char_corr, ttl_chars, char_corr == ttl_chars)
+		(char_corr, ttl_chars, char_corr == ttl_chars)
 	}
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum AppState {
 	Menu,
 	Typing,
 	Stats,
+	/// the saved tests that can be played back
+	Replays,
+	/// a test being played back
+	Replay,
+	/// waiting between races
+	Lobby,
+	/// past results, with trends
+	History,
 }
 
 pub struct App {
 	pub state:			AppState,
 	pub quit:			bool,
+	/// Esc was pressed in the menu, a second press quits
+	pub quit_pending:	bool,
+	/// Tab was pressed, so Enter restarts the test
+	pub restart_pending: bool,
 	pub active_session:	Session,
 	pub active_stats:	SessionStats,
-	default_text:		Option<String>,
-	default_word_count: Option<usize>,
+	pub history:		History,
+	/// best wpm for the finished test's mode before it was recorded
+	pub previous_best:	Option<f32>,
+	pub is_new_best:	bool,
+	pub history_error:	Option<String>,
+	/// every test finished since starting, for `--json` and `--csv`
+	pub results:		Vec<TestResult>,
+	/// which past results the history screen shows
+	pub history_filter:	HistoryFilter,
+	/// index into `FilterItem::ALL` of the highlighted filter
+	pub filter_item:	usize,
+	/// index into `history_results()` of the highlighted result, None
+	/// while it's a filter that is
+	pub result_item:	Option<usize>,
+	/// per character totals over every test taken
+	pub key_stats:		KeyStats,
+	/// the caret racing the current test, if there's a pace set
+	pub ghost:			Option<Ghost>,
+	/// the test being played back, in the replay state
+	pub replay:			Option<Replay>,
+	/// where Esc goes back to from a replay
+	pub replay_from:	AppState,
+	/// index into `replayable()` of the highlighted saved test
+	pub replay_item:	usize,
+	/// the race being hosted or joined with `term-type host` or `join`
+	pub race:			Option<Race>,
+	/// when typing starts in the current test, None for tests that aren't
+	/// races
+	pub race_start:		Option<Instant>,
+	pub race_error:		Option<String>,
+	/// what the next test will be, as chosen in the menu
+	pub settings:		Settings,
+	/// what's kept for the next run: `settings` without what came from the
+	/// command line, other than menu changes to it
+	pub saved_settings:	Settings,
+	/// index into `MenuItem::ALL` of the highlighted menu row
+	pub menu_item:		usize,
+	pub menu_error:		Option<String>,
+	/// the list random words are drawn from, loaded from `settings`
+	pub word_list:		WordList,
+	/// the file code tests type, loaded from `settings`
+	pub source:			Option<CodeGenerator>,
+	/// punctuation and number rates. Whether they're on comes from `settings`.
+	pub modifiers:		Modifiers,
+	/// weighting and repeats of random words
+	pub sampling:		Sampling,
+	/// what the next test is drawn with, instead of a random seed
+	pub seed:			Option<u64>,
+	/// show wpm, accuracy and progress while typing
+	pub show_live_stats: bool,
+	/// loaded from `settings`
+	pub theme:			Theme,
+	/// themes from the config file, by name
+	pub custom_themes:	HashMap<String, ThemeConfig>,
+	pub keys:			KeyBindings,
 }
 
 impl App {
-	pub fn default() -> Self {
+	pub fn from(settings: Settings) -> Self {
 		Self {
 			state: AppState::Menu,
 			active_session: Session::default(),
 			active_stats: SessionStats::default(),
+			history: History::default(),
+			previous_best: None,
+			is_new_best: false,
+			history_error: None,
+			results: vec![],
+			history_filter: HistoryFilter::default(),
+			filter_item: 0,
+			result_item: None,
+			key_stats: KeyStats::default(),
+			ghost: None,
+			replay: None,
+			replay_from: AppState::Menu,
+			replay_item: 0,
+			race: None,
+			race_start: None,
+			race_error: None,
+			saved_settings: settings.clone(),
+			settings,
+			menu_item: 0,
+			menu_error: None,
+			word_list: WordList::default(),
+			source: None,
+			modifiers: Modifiers::default(),
+			sampling: Sampling::default(),
+			seed: None,
+			show_live_stats: false,
+			theme: Theme::default(),
+			custom_themes: HashMap::new(),
+			keys: KeyBindings::default(),
 			quit: false,
-			default_text: None,
-			default_word_count: None,
+			quit_pending: false,
+			restart_pending: false,
 		}
 	}
 
-	pub fn from_str(default_text: String) -> Self {
-		Self {
-			state: AppState::Menu,
-			active_session: Session::default(),
-			active_stats: SessionStats::default(),
-			quit: false,
-			default_text: Some(default_text),
-			default_word_count: None,
+	/// Loads the word list, source file and theme named in `settings`
+	pub fn apply_settings(&mut self) -> Result<(), String> {
+		let word_list = if let Some(path) = &self.settings.wordlist {
+			WordList::from_file(path)
+		} else if let Some(language) = &self.settings.language {
+			WordList::installed(language)
+		} else {
+			Ok(WordList::default())
+		};
+		self.word_list = word_list.map_err(|e| e.to_string())?;
+
+		self.source = match &self.settings.source {
+			Some(path) => Some(CodeGenerator::from_file(path).map_err(|e| e.to_string())?),
+			None => None,
+		};
+		let languages = code::languages();
+		if !languages.contains(&self.settings.code_language.as_str()) {
+			return Err(format!("there are no snippets in '{}' (available: {})",
+				self.settings.code_language, languages.join(", ")));
 		}
+
+		self.theme = Theme::named(&self.settings.theme, &self.custom_themes)?;
+		Ok(())
 	}
 
-	pub fn with_word_count(word_count: usize) -> Self {
-		Self {
-			state: AppState::Menu,
-			active_session: Session::default(),
-			active_stats: SessionStats::default(),
-			quit: false,
-			default_text: None,
-			default_word_count: Some(word_count),
+	/// Leaves a test or the stats screen, or a replay for wherever it was
+	/// opened from. In the menu it asks for confirmation before quitting.
+	pub fn on_esc(&mut self) {
+		self.restart_pending = false;
+		match self.state {
+			AppState::Menu if self.quit_pending => self.quit = true,
+			AppState::Menu => self.quit_pending = true,
+			AppState::Replay => {
+				self.replay = None;
+				self.state = self.replay_from;
+			},
+			// the menu is still there between races, to change the next one
+			AppState::Lobby => self.state = AppState::Menu,
+			_ => self.open_menu(),
 		}
 	}
 
-	pub fn on_esc(&mut self) {
+	pub fn on_quit(&mut self) {
 		self.quit = true;
 	}
 
+	/// First half of restart+Enter (Tab+Enter by default), which restarts
+	/// with new words
+	pub fn on_restart(&mut self) {
+		self.quit_pending = false;
+		self.restart_pending = matches!(self.state, AppState::Typing | AppState::Stats) && !self.is_racing();
+	}
+
 	pub fn on_enter(&mut self) {
+		self.quit_pending = false;
+		if std::mem::take(&mut self.restart_pending) {
+			self.open_typing();
+			return;
+		}
+
 		match self.state {
 			AppState::Menu => self.open_typing(),
 			AppState::Stats => self.open_menu(),
-			_ => {}, // do nothing in typing mode	
+			// only code tests have any use for Enter
+			AppState::Typing if self.countdown().is_none() => self.active_session.on_enter(),
+			AppState::Typing => {},
+			AppState::Replays => {
+				let entry = self.replayable().get(self.replay_item).map(|entry| (*entry).clone());
+				if let Some(entry) = entry {
+					self.open_replay(&entry);
+				}
+			},
+			AppState::History => {
+				let entry = self.result_item
+					.and_then(|i| self.history_results().get(i).map(|entry| (*entry).clone()));
+				if let Some(entry) = entry.filter(|entry| entry.can_replay()) {
+					self.open_replay(&entry);
+				}
+			},
+			AppState::Replay => {},
+			// only the host can start a race
+			AppState::Lobby => if self.race.as_ref().is_some_and(|race| race.is_host()) {
+				self.open_typing();
+			},
+		}
+		self.check_state();
+	}
+
+	/// Starts over on the same words as the current or last test
+	pub fn on_repeat(&mut self) {
+		self.clear_pending();
+		if matches!(self.state, AppState::Typing | AppState::Stats) && !self.is_racing() {
+			self.active_session = self.active_session.repeat();
+			self.ghost = Ghost::new(self.settings.pace, &self.active_session, &self.history);
+			self.state = AppState::Typing;
+			self.start_race();
 		}
 	}
 
 	pub fn on_space(&mut self) {	
+		self.clear_pending();
 		match self.state {
-			AppState::Typing => self.active_session.on_space(),
-			_ => {}, // do nothing in menu or stats
+			AppState::Typing if self.countdown().is_none() => self.active_session.on_space(),
+			AppState::Replay => if let Some(replay) = &mut self.replay {
+				replay.toggle_pause();
+			},
+			_ => {},
 		}
 		self.check_state();
 	}
 
 	pub fn on_key(&mut self, c: char) {	
+		self.clear_pending();
 		match self.state {
-			AppState::Typing => self.active_session.on_char(c),
-			_ => {}, // do nothing in menu or stats (TODO)
+			AppState::Typing if self.countdown().is_none() => self.active_session.on_char(c),
+			// vim keys work in the menu too
+			AppState::Menu if c == 'p' => self.open_replays(),
+			AppState::Menu if c == 'r' => self.open_history(),
+			AppState::Stats if c == 'p' => {
+				let entry = HistoryEntry::new(&self.active_session, &self.active_stats);
+				self.open_replay(&entry);
+			},
+			// vim keys work outside of tests too
+			AppState::Menu | AppState::Replays | AppState::Replay | AppState::History => match c {
+				'k' => self.on_up(),
+				'j' => self.on_down(),
+				'h' => self.on_left(),
+				'l' => self.on_right(),
+				_ => {},
+			},
+			AppState::Typing | AppState::Stats | AppState::Lobby => {},
 		}
 		self.check_state();
 	}
 
-	pub fn on_del(&mut self) {	
+	/// Ends a zen test
+	pub fn on_finish(&mut self) {
+		self.clear_pending();
+		if self.state == AppState::Typing && self.countdown().is_none() {
+			self.active_session.finish();
+		}
+		self.check_state();
+	}
+
+	pub fn on_up(&mut self) {
+		self.clear_pending();
 		match self.state {
-			AppState::Typing => self.active_session.on_del(),
-			_ => {}, // do nothing in menu or stats
+			AppState::Menu => {
+				let len = MenuItem::ALL.len();
+				self.menu_item = (self.menu_item + len - 1) % len;
+			},
+			AppState::Replays => self.replay_item = self.replay_item.saturating_sub(1),
+			// up from the first result is back to the filters
+			AppState::History => match self.result_item {
+				Some(0) => self.result_item = None,
+				Some(i) => self.result_item = Some(i - 1),
+				None => self.filter_item = self.filter_item.saturating_sub(1),
+			},
+			AppState::Replay => if let Some(replay) = &mut self.replay {
+				replay.change_speed(true);
+			},
+			_ => {},
 		}
 	}
 
-	pub fn check_state(&mut self) {
+	pub fn on_down(&mut self) {
+		self.clear_pending();
 		match self.state {
-			AppState::Typing => {
-				if self.active_session.state == SessionState::Finished {
-					self.open_stats();
+			AppState::Menu => self.menu_item = (self.menu_item + 1) % MenuItem::ALL.len(),
+			// the results carry on below the filters
+			AppState::History => {
+				let results = self.history_results().len();
+				match self.result_item {
+					Some(i) => self.result_item = Some((i + 1).min(results.saturating_sub(1))),
+					None if self.filter_item + 1 < FilterItem::ALL.len() => self.filter_item += 1,
+					None if results > 0 => self.result_item = Some(0),
+					None => {},
 				}
 			},
-			_ => {}
+			AppState::Replays => {
+				let last = self.replayable().len().saturating_sub(1);
+				self.replay_item = (self.replay_item + 1).min(last);
+			},
+			AppState::Replay => if let Some(replay) = &mut self.replay {
+				replay.change_speed(false);
+			},
+			_ => {},
+		}
+	}
+
+	pub fn on_left(&mut self) {
+		match (&mut self.replay, self.state) {
+			(Some(replay), AppState::Replay) => replay.step(false),
+			(_, AppState::History) => self.change_filter(false),
+			_ => self.change_setting(false),
+		}
+	}
+
+	pub fn on_right(&mut self) {
+		match (&mut self.replay, self.state) {
+			(Some(replay), AppState::Replay) => replay.step(true),
+			(_, AppState::History) => self.change_filter(true),
+			_ => self.change_setting(true),
+		}
+	}
+
+	/// Saved tests that can be played back, newest first
+	pub fn replayable(&self) -> Vec<&HistoryEntry> {
+		self.history.entries().iter()
+			.rev()
+			.filter(|entry| entry.can_replay())
+			.collect()
+	}
+
+	pub fn on_del(&mut self) {	
+		self.clear_pending();
+		if self.state == AppState::Typing && self.countdown().is_none() {
+			self.active_session.on_del();
+		}
+		self.check_state();
+	}
+
+	pub fn on_tick(&mut self) {
+		if let Some(race) = &mut self.race
+			&& let Some(start) = race.poll() {
+			self.join_race(start);
+		}
+
+		match (&mut self.replay, self.state) {
+			(_, AppState::Typing) => self.active_session.on_tick(),
+			(Some(replay), AppState::Replay) => replay.on_tick(),
+			_ => {},
+		}
+		self.check_state();
+
+		if self.is_racing() && self.countdown().is_none()
+			&& let Some(race) = &mut self.race {
+			race.report(Progress::from(&self.active_session));
+		}
+	}
+
+	/// Time left before typing starts in a race, None once it has (and
+	/// for tests that aren't races)
+	pub fn countdown(&self) -> Option<Duration> {
+		self.race_start
+			.map(|start| start.saturating_duration_since(Instant::now()))
+			.filter(|left| !left.is_zero())
+	}
+
+	/// In the middle of a race, which can't be restarted or repeated
+	pub fn is_racing(&self) -> bool {
+		self.state == AppState::Typing && self.race_start.is_some()
+	}
+
+	pub fn check_state(&mut self) {
+		if self.state == AppState::Typing 
+			&& self.active_session.state == SessionState::Finished {
+			self.open_stats();
 		}
 	}
 
 	// helpers
+	fn clear_pending(&mut self) {
+		self.restart_pending = false;
+		self.quit_pending = false;
+	}
 	fn open_typing(&mut self) {
-		if let Some(default_text) = &self.default_text {
-			self.active_session = Session::from(default_text.clone());
-		} else if let Some(default_count) = self.default_word_count {
-			self.active_session = Session::random_with_size(default_count);
-		} else {
-			self.active_session = Session::default();
-		}
+		let settings = &self.settings;
+		// kept small so test codes stay short
+		let seed = self.seed.take().unwrap_or_else(|| rand::random::<u32>() as u64);
+		let modifiers = Modifiers {
+			punctuation: settings.punctuation,
+			numbers: settings.numbers,
+			..self.modifiers
+		};
+
+		self.active_session = match (settings.mode, &settings.text) {
+			(ModeName::Custom, Some(text)) => Session::from(text.clone()),
+			(ModeName::Time, _) => Session::timed(Duration::from_secs(settings.time), &self.word_list, self.sampling, seed)
+				.with_modifiers(modifiers),
+			(ModeName::Quote, _) => Session::quote(settings.quote, seed),
+			(ModeName::Zen, _) => Session::zen((settings.zen_idle > 0)
+				.then(|| Duration::from_secs(settings.zen_idle))),
+			(ModeName::Code, _) => match &self.source {
+				Some(source) => Session::code(source.clone(), settings.skip_indent, None),
+				None => {
+					let snippet = CodeGenerator::snippet(&settings.code_language, &mut StdRng::seed_from_u64(seed))
+						.expect("expected snippets in a language checked by apply_settings");
+					Session::code(snippet, settings.skip_indent, Some(seed))
+				},
+			},
+			(ModeName::Adaptive, _) => Session::adaptive(settings.words, &self.word_list, self.sampling, &self.key_stats, seed)
+				.with_modifiers(modifiers),
+			_ => Session::random_with_size(settings.words, &self.word_list, self.sampling, seed)
+				.with_modifiers(modifiers),
+		};
+		self.ghost = Ghost::new(settings.pace, &self.active_session, &self.history);
 		
 		self.state = AppState::Typing;
+		self.start_race();
+	}
+	// while hosting, every test started is raced by everyone who's joined
+	fn start_race(&mut self) {
+		self.race_start = None;
+		let Some(Race::Host(host)) = &mut self.race else {
+			return;
+		};
+
+		match TestCode::from(&self.active_session) {
+			Some(code) => {
+				host.start(&code);
+				self.race_start = Some(Instant::now() + COUNTDOWN);
+				self.race_error = None;
+			},
+			None => {
+				self.race_error = Some(format!("{} tests can't be raced, only words, time and quote tests",
+					self.active_session.mode));
+				self.state = AppState::Lobby;
+			},
+		}
+	}
+	// the host has started a race, which takes over from whatever was going on
+	fn join_race(&mut self, start: Start) {
+		match start.code.session() {
+			Ok(session) => {
+				self.active_session = session;
+				self.ghost = Ghost::new(self.settings.pace, &self.active_session, &self.history);
+				self.replay = None;
+				self.race_start = Some(Instant::now() + start.countdown);
+				self.race_error = None;
+				self.state = AppState::Typing;
+			},
+			Err(e) => {
+				self.race_error = Some(format!("couldn't join the race: {}", e));
+				self.race_start = None;
+				self.state = AppState::Lobby;
+			},
+		}
 	}
 	fn open_stats(&mut self) {
 		self.active_stats = SessionStats::from(&self.active_session);
+		self.record_result();
+		if self.race_start.is_some()
+			&& let Some(race) = &mut self.race {
+			race.report(Progress::finished(&self.active_stats));
+		}
 		self.state = AppState::Stats;
 	}
+	fn record_result(&mut self) {
+		let entry = HistoryEntry::new(&self.active_session, &self.active_stats);
+		let code = TestCode::from(&self.active_session).map(|code| code.to_string());
+		self.results.push(TestResult::from(&entry, code));
+		
+		self.previous_best = self.history.personal_best(&entry).map(|e| e.stats.wpm);
+		self.is_new_best = match self.previous_best {
+			Some(best) => self.active_stats.wpm > best,
+			None => true,
+		};
+		
+		self.history_error = self.history.record(entry).err()
+			.map(|e| format!("couldn't save result: {}", e));
+
+		let test_keys = KeyStats::from_keystrokes(&self.active_session.keystrokes);
+		if let Err(e) = self.key_stats.record(&test_keys) {
+			self.history_error.get_or_insert(format!("couldn't save key stats: {}", e));
+		}
+	}
 	fn open_menu(&mut self) {
-		self.state = AppState::Menu;
+		self.race_start = None;
+		// racers wait for the next race in the lobby
+		self.state = if self.race.is_some() { AppState::Lobby } else { AppState::Menu };
+	}
+	fn open_replays(&mut self) {
+		self.replay_item = 0;
+		self.state = AppState::Replays;
+	}
+	fn open_history(&mut self) {
+		self.filter_item = 0;
+		self.result_item = None;
+		self.state = AppState::History;
+	}
+
+	/// The results the history screen lists
+	pub fn history_results(&self) -> Vec<&HistoryEntry> {
+		self.history.filtered(&self.history_filter, history::now())
+	}
+	// every filter can be turned off, so None comes first among its options
+	fn change_filter(&mut self, forward: bool) {
+		self.clear_pending();
+		let filter = &mut self.history_filter;
+		let item = FilterItem::ALL[self.filter_item];
+		if self.result_item.is_some() || !item.applies_to(filter) {
+			return;
+		}
+
+		fn or_any<T>(options: Vec<T>) -> Vec<Option<T>> {
+			std::iter::once(None).chain(options.into_iter().map(Some)).collect()
+		}
+		match item {
+			FilterItem::Sort => filter.sort = cycle(&SORT_ORDERS, &filter.sort, forward),
+			FilterItem::Mode => {
+				filter.mode = cycle(&or_any(self.history.modes()), &filter.mode, forward);
+				filter.length = None;
+			},
+			FilterItem::Length => if let Some(mode) = filter.mode {
+				filter.length = cycle(&or_any(self.history.lengths(mode)), &filter.length, forward);
+			},
+			FilterItem::WordList =>
+				filter.word_list = cycle(&or_any(self.history.word_lists()), &filter.word_list, forward),
+			FilterItem::Dates => filter.days = cycle(&DATE_RANGES, &filter.days, forward),
+		}
+	}
+	fn open_replay(&mut self, entry: &HistoryEntry) {
+		self.replay = Some(Replay::from(entry));
+		self.replay_from = self.state;
+		self.state = AppState::Replay;
+	}
+	fn change_setting(&mut self, forward: bool) {
+		self.clear_pending();
+		if self.state != AppState::Menu {
+			return;
+		}
+
+		let item = MenuItem::ALL[self.menu_item];
+		let previous = self.settings.clone();
+		let settings = &mut self.settings;
+		if !item.applies_to(settings.mode) {
+			return;
+		}
+
+		match item {
+			MenuItem::Mode => settings.mode = cycle(&settings.modes(), &settings.mode, forward),
+			MenuItem::Length => match settings.mode {
+				ModeName::Words | ModeName::Adaptive =>
+					settings.words = cycle_number(&WORD_COUNTS, settings.words, forward),
+				ModeName::Time => settings.time = cycle_number(&TIME_LIMITS, settings.time, forward),
+				ModeName::Quote => settings.quote = cycle(&QUOTE_LENGTHS, &settings.quote, forward),
+				ModeName::Zen => settings.zen_idle = cycle_number(&ZEN_IDLE_LIMITS, settings.zen_idle, forward),
+				ModeName::Code => {
+					// like word lists, a --source file stays on offer until a language is picked
+					let mut names: Vec<String> = code::languages().iter().map(|n| n.to_string()).collect();
+					let current = settings.code_name();
+					if settings.source.is_some() {
+						names.insert(0, current.clone());
+					}
+					let next = cycle(&names, &current, forward);
+					if next != current {
+						settings.source = None;
+						settings.code_language = next;
+					}
+				},
+				ModeName::Custom => {},
+			},
+			MenuItem::WordList => {
+				// a file from --wordlist stays on offer until something else is picked
+				let mut names = wordlist::available();
+				let current = settings.word_list_name();
+				if settings.wordlist.is_some() {
+					names.insert(0, current.clone());
+				}
+				let next = cycle(&names, &current, forward);
+				if next != current {
+					settings.wordlist = None;
+					settings.language = Some(next);
+				}
+			},
+			MenuItem::Punctuation => settings.punctuation = !settings.punctuation,
+			MenuItem::Numbers => settings.numbers = !settings.numbers,
+			MenuItem::Indentation => settings.skip_indent = !settings.skip_indent,
+			MenuItem::Pace => settings.pace = cycle(&PACES, &settings.pace, forward),
+			MenuItem::Theme => {
+				let mut names: Vec<String> = BUILTIN_THEMES.iter().map(|n| n.to_string()).collect();
+				let mut custom: Vec<String> = self.custom_themes.keys()
+					.filter(|name| !BUILTIN_THEMES.contains(&name.as_str()))
+					.cloned()
+					.collect();
+				custom.sort();
+				names.append(&mut custom);
+				settings.theme = cycle(&names, &settings.theme, forward);
+			},
+		}
+
+		// a choice that can't be loaded is undone rather than saved. Flags
+		// from the command line only last the run, so only what changed is.
+		self.menu_error = match self.apply_settings() {
+			Ok(()) => {
+				self.saved_settings.copy_item(&self.settings, item);
+				self.saved_settings.save().err()
+					.map(|e| format!("couldn't save settings: {}", e))
+			},
+			Err(e) => {
+				self.settings = previous;
+				let _ = self.apply_settings();
+				Some(e)
+			},
+		};
 	}
 }
 
 #[cfg(test)]
 mod app_tests {
 	use super::*;
+	use crate::ghost::Pace;
+	use crate::race::race_tests::wait_until;
+	use crate::wordlist::Weighting;
+
+	fn custom_app(text: &str) -> App {
+		let mut settings = Settings::default();
+		settings.mode = ModeName::Custom;
+		settings.text = Some(text.to_string());
+		App::from(settings)
+	}
 
 	#[test]
 	fn test_1() {
-		let mut app = App::default();	
+		let mut app = custom_app("The quick brown fox jumps over the lazy dog");
 		app.on_enter();
 		assert_eq!(app.state, AppState::Typing);
 		app.on_key('a');
@@ -566,7 +1820,7 @@ mod app_tests {
 
 	#[test]
 	fn test_2() {
-		let mut app = App::default();
+		let mut app = custom_app("The quick brown fox jumps over the lazy dog");
 		app.open_typing();
 		for c in "The quick brown fox jumps over the lazy dog".chars() {
 			if c == ' '{
@@ -586,7 +1840,7 @@ mod app_tests {
 			vec!["The","quick","brown","fox","jumps","over","the","lazy","dog"]
 		);
 
-		let acc_difference = (app.active_stats.acc - 1.0).abs();
+		let acc_difference = (app.active_stats.acc.unwrap() - 1.0).abs();
 		assert!(acc_difference < 0.0001);
 	}
 	
@@ -594,11 +1848,11 @@ mod app_tests {
 	fn test_3() {
 		let mut session = Session::from("a b cd".to_string());
 
-		assert_eq!(session.target_words.get_word_at(0),
+		assert_eq!(session.target_word(0),
 			Some("a".to_string()));
-		assert_eq!(session.target_words.get_word_at(1),
+		assert_eq!(session.target_word(1),
 			Some("b".to_string()));
-		assert_eq!(session.target_words.get_word_at(2),
+		assert_eq!(session.target_word(2),
 			Some("cd".to_string()));
 
 		session.on_char('a');
@@ -620,12 +1874,12 @@ mod app_tests {
 		let stats = SessionStats::from(&session);
 		
 		// MonkeyType will yield 2/3 accuracy in this situation
-		let target_acc: f32 = 4.0 / 5.0;
+		let target_acc: f32 = 2.0 / 3.0;
 
-		let acc_difference = (stats.acc - target_acc).abs();
+		let acc_difference = (stats.acc.unwrap() - target_acc).abs();
 		assert!(
 			acc_difference < 0.0001, "Accuracy was wrong ({} vs {})", 
-			stats.acc, 
+			stats.acc.unwrap(), 
 			target_acc
 		);
 		
@@ -636,5 +1890,558 @@ mod app_tests {
 		assert_eq!(stats.word_total, 3);
 	}
 
+	#[test]
+	fn test_last_word_mistyped() {
+		// random words have no target text, only the generator's length
+		let mut session = Session::random_with_size(2, &WordList::default(), Sampling::default(), 1);
+		let first = session.target_word(0).unwrap();
+		for c in first.chars() {
+			session.on_char(c);
+		}
+		session.on_space();
+		session.on_char('Z');
+		session.on_space();
+
+		assert_eq!(session.input, vec![first, "Z".to_string()]);
+		assert_eq!(session.state, SessionState::Finished);
+	}
+
+	#[test]
+	fn test_keystroke_log() {
+		let mut session = Session::from("ab cd ef".to_string());
+
+		session.on_char('a');
+		session.on_char('x');
+		session.on_del();
+		session.on_char('b');
+		session.on_space();
+		session.on_space(); // ignored, the word is empty
+		for c in "cd ef".chars() {
+			if c == ' ' {
+				session.on_space();
+			} else {
+				session.on_char(c);
+			}
+		}
+
+		assert_eq!(session.state, SessionState::Finished);
+		let actions: Vec<KeyAction> = session.keystrokes.iter()
+			.map(|k| k.action)
+			.collect();
+		assert_eq!(actions, vec![
+			KeyAction::Char('a'), KeyAction::Char('x'), KeyAction::Delete,
+			KeyAction::Char('b'), KeyAction::Space, KeyAction::Char('c'),
+			KeyAction::Char('d'), KeyAction::Space, KeyAction::Char('e'),
+			KeyAction::Char('f'),
+		]);
+		assert_eq!(session.keystrokes[1].expected, Some('b'));
+		assert!(!session.keystrokes[1].correct);
+		assert!(session.keystrokes[4].correct);
+
+		let stats = SessionStats::from(&session);
+
+		// the corrected 'x' still costs accuracy
+		let acc_difference = (stats.acc.unwrap() - 8.0 / 9.0).abs();
+		assert!(acc_difference < 0.0001, "Accuracy was wrong ({:?})", stats.acc);
+		assert_eq!(stats.word_corr, 3);
+	}
+
+	#[test]
+	fn test_live_stats() {
+		let mut session = Session::from("ab cd ef".to_string());
+		assert_eq!(session.live_stats().words_done, 0);
+		assert_eq!(session.live_stats().acc, 1.0);
+
+		for c in "ax".chars() {
+			session.on_char(c);
+		}
+		session.on_del();
+		session.on_char('b');
+		session.on_space();
+		session.on_char('c');
+
+		let live = session.live_stats();
+		assert_eq!(live.words_done, 1);
+		assert_eq!(live.words_total, Some(3));
+		assert!((live.acc - 0.8).abs() < 0.0001);
+		// "ab c" under a second in, without the deleted 'x'
+		assert!((live.wpm_raw - 48.0).abs() < 0.001);
+		assert!((live.wpm - 48.0).abs() < 0.001);
+
+		session.on_char('x');
+		let live = session.live_stats();
+		assert!((live.wpm_raw - 60.0).abs() < 0.001);
+		assert!((live.wpm - 36.0).abs() < 0.001);
+
+		// deleting back into "ab" takes its space off again
+		for _ in 0..3 {
+			session.on_del();
+		}
+		let live = session.live_stats();
+		assert_eq!(live.words_done, 0);
+		assert!((live.wpm_raw - 24.0).abs() < 0.001);
+		assert!((live.wpm - 24.0).abs() < 0.001);
+	}
+
+	#[test]
+	fn test_word_compare() {
+		assert_eq!(SessionStats::word_compare("café", "café"), (4, 4, true));
+		assert_eq!(SessionStats::word_compare("cafe", "café"), (3, 4, false));
+		assert_eq!(SessionStats::word_compare("caféé", "café"), (4, 5, false));
+	}
+
+	#[test]
+	fn test_timeline() {
+		let key = |time_ms, correct| Keystroke { 
+			time_ms, word: 0, action: KeyAction::Char('a'), expected: Some('a'), correct 
+		};
+		let keystrokes = vec![
+			key(0, true), key(300, true), key(600, false),
+			key(1200, true), key(1500, true),
+		];
+
+		let timeline = SessionStats::timeline(&keystrokes, 1.5);
+		assert_eq!(timeline.len(), 2);
+
+		assert_eq!(timeline[0].time_s, 1.0);
+		assert_eq!(timeline[0].errors, 1);
+		assert!((timeline[0].wpm_raw - 36.0).abs() < 0.0001);
+		assert!((timeline[0].wpm - 24.0).abs() < 0.0001);
+
+		// the final keystroke lands exactly on the end of the session
+		assert_eq!(timeline[1].time_s, 1.5);
+		assert_eq!(timeline[1].errors, 0);
+		assert!((timeline[1].wpm_raw - 48.0).abs() < 0.0001);
+		assert!((timeline[1].wpm - 32.0).abs() < 0.0001);
+
+		// a few keys in the last 50ms join the second before them
+		let keystrokes = vec![key(500, true), key(1500, true), key(2020, true), key(2040, true)];
+		let timeline = SessionStats::timeline(&keystrokes, 2.05);
+		assert_eq!(timeline.len(), 2);
+		assert_eq!(timeline[1].time_s, 2.05);
+		assert!((timeline[1].wpm_raw - 3.0 / 5.0 / (1.05 / 60.0)).abs() < 0.0001);
+	}
+
+	#[test]
+	fn test_consistency_and_burst() {
+		let sample = |wpm_raw| WpmSample { wpm_raw, ..Default::default() };
+
+		let steady = vec![sample(60.0), sample(60.0), sample(60.0)];
+		assert!((SessionStats::consistency(&steady) - 1.0).abs() < 0.0001);
+		let uneven = vec![sample(20.0), sample(100.0), sample(60.0)];
+		assert!(SessionStats::consistency(&uneven) < 0.6);
+
+		// a steady 60 wpm for 3.6s, with one stray key in the last 0.6s
+		let key = |time_ms| Keystroke {
+			time_ms, word: 0, action: KeyAction::Char('a'), expected: Some('a'), correct: true
+		};
+		let keystrokes: Vec<Keystroke> = (0..15).map(|i| key(i * 200)).chain([key(3500)]).collect();
+		let timeline = SessionStats::timeline(&keystrokes, 3.6);
+		assert_eq!(timeline.len(), 4);
+		assert_eq!(SessionStats::whole_seconds(&timeline).len(), 3);
+		assert!((SessionStats::consistency(SessionStats::whole_seconds(&timeline)) - 1.0).abs() < 0.0001);
+		assert!(SessionStats::consistency(&timeline) < 0.9);
+
+		let key = |time_ms, word, action| Keystroke {
+			time_ms, word, action, expected: None, correct: true
+		};
+		let keystrokes = vec![
+			key(0, 0, KeyAction::Char('a')),
+			key(600, 0, KeyAction::Char('b')),
+			key(1200, 0, KeyAction::Space),
+			key(1500, 1, KeyAction::Char('c')),
+			key(1800, 1, KeyAction::Char('d')),
+		];
+		let input = vec!["ab".to_string(), "cd".to_string()];
+		
+		let bursts = SessionStats::word_bursts(&keystrokes, &input);
+		assert_eq!(bursts.len(), 2);
+		assert!((bursts[0] - 20.0).abs() < 0.0001);
+		assert!((bursts[1] - 40.0).abs() < 0.0001);
+	}
+
+	#[test]
+	fn test_random_sampling() {
+		let word_list = WordList {
+			name: "test".to_string(),
+			words: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+			counts: Some(vec![1, 0, 1]),
+		};
+
+		let sampling = Sampling { weighting: Weighting::Frequency, no_repeat: 0 };
+		let generator = RandomWordGenerator::with_size(100, &word_list, sampling, 1);
+		assert!(!generator.words.contains(&"b".to_string()));
+
+		// only two words can be drawn, so they have to alternate
+		let sampling = Sampling { weighting: Weighting::Frequency, no_repeat: 5 };
+		let generator = RandomWordGenerator::with_size(100, &word_list, sampling, 1);
+		assert!(generator.words.windows(2).all(|pair| pair[0] != pair[1]));
+
+		let sampling = Sampling { weighting: Weighting::Uniform, no_repeat: 2 };
+		let generator = RandomWordGenerator::with_size(100, &word_list, sampling, 1);
+		assert!(generator.words.windows(3)
+			.all(|words| words[0] != words[1] && words[1] != words[2] && words[0] != words[2]));
+	}
+
+	#[test]
+	fn test_timed() {
+		let mut session = Session::timed(Duration::from_millis(50), &WordList::default(), Sampling::default(), 1);
+		assert_eq!(session.target_words.as_ref().unwrap().len(), None);
+		assert!(session.target_word(500).is_some());
+
+		for c in "ab cd".chars() {
+			if c == ' ' {
+				session.on_space();
+			} else {
+				session.on_char(c);
+			}
+		}
+		assert_eq!(session.state, SessionState::Active);
+
+		std::thread::sleep(Duration::from_millis(60));
+		session.on_char('x');
+		assert_eq!(session.state, SessionState::Finished);
+		assert_eq!(session.input, vec!["ab".to_string(), "cd".to_string()]);
+		assert_eq!(session.get_final_duration_s(), Some(0.05));
+
+		let stats = SessionStats::from(&session);
+		assert_eq!(stats.word_total, 2);
+	}
+
+	#[test]
+	fn test_restart_and_repeat() {
+		let mut settings = Settings::default();
+		settings.words = 10;
+		let mut app = App::from(settings);
+		app.on_enter();
+		app.on_key('x');
+
+		// Tab+Enter starts over with new words
+		app.on_restart();
+		app.on_enter();
+		assert_eq!(app.state, AppState::Typing);
+		assert_eq!(app.active_session.input, vec![""]);
+
+		let words: Vec<String> = (0..10)
+			.map(|i| app.active_session.target_word(i).unwrap())
+			.collect();
+		app.on_key('x');
+		app.on_repeat();
+		assert_eq!(app.active_session.input, vec![""]);
+		assert!(app.active_session.keystrokes.is_empty());
+		let repeated: Vec<String> = (0..10)
+			.map(|i| app.active_session.target_word(i).unwrap())
+			.collect();
+		assert_eq!(repeated, words);
+
+		// Tab followed by anything else doesn't restart
+		app.on_restart();
+		app.on_key('y');
+		app.on_enter();
+		assert_eq!(app.active_session.input, vec!["y"]);
+
+		// Esc goes back to the menu, and only quits from there when confirmed
+		app.on_esc();
+		assert_eq!(app.state, AppState::Menu);
+		app.on_esc();
+		assert!(app.quit_pending);
+		assert!(!app.quit);
+		app.on_esc();
+		assert!(app.quit);
+	}
+
+	#[test]
+	fn test_menu() {
+		let mut app = App::from(Settings::default());
+		assert_eq!(MenuItem::ALL[app.menu_item], MenuItem::Mode);
+
+		// custom isn't offered without any text to type
+		app.on_left();
+		assert_eq!(app.settings.mode, ModeName::Zen);
+		app.on_left();
+		assert_eq!(app.settings.mode, ModeName::Code);
+		app.on_left();
+		assert_eq!(app.settings.mode, ModeName::Adaptive);
+		app.on_left();
+		assert_eq!(app.settings.mode, ModeName::Quote);
+		for _ in 0..5 {
+			app.on_right();
+		}
+		assert_eq!(app.settings.mode, ModeName::Time);
+
+		app.on_down();
+		app.on_right();
+		assert_eq!(app.settings.time, 60);
+
+		// rows that don't apply to the mode are left alone
+		app.on_key('k');
+		app.on_key('h');
+		app.on_key('h');
+		assert_eq!(app.settings.mode, ModeName::Zen);
+		app.on_key('j');
+		app.on_key('j');
+		app.on_key('j');
+		app.on_right();
+		assert!(!app.settings.punctuation);
+
+		app.on_up();
+		app.on_up();
+		app.on_up();
+		app.on_right();
+		assert_eq!(app.settings.mode, ModeName::Words);
+		for _ in 0..3 {
+			app.on_down();
+		}
+		app.on_right();
+		assert!(app.settings.punctuation);
+
+		for _ in 0..3 {
+			app.on_down();
+		}
+		app.on_right();
+		assert_eq!(app.settings.pace, Pace::Best);
+		app.on_down();
+		app.on_right();
+		assert_eq!(app.settings.theme, "light");
+		assert_eq!(app.theme, Theme::light());
+
+		app.on_enter();
+		assert_eq!(app.state, AppState::Typing);
+		assert_eq!(app.active_session.mode, TestMode::Words(25));
+		assert!(app.active_session.modifiers.punctuation);
+	}
+
+	#[test]
+	fn test_zen() {
+		let mut session = Session::zen(Some(Duration::from_millis(50)));
+		assert!(session.is_zen());
+		for c in "hi  there ".chars() {
+			if c == ' ' {
+				session.on_space();
+			} else {
+				session.on_char(c);
+			}
+		}
+		assert_eq!(session.input, vec!["hi", "there", ""]);
+		assert!(session.keystrokes.iter().all(|k| k.correct && k.expected.is_none()));
+
+		session.on_tick();
+		assert_eq!(session.state, SessionState::Active);
+		std::thread::sleep(Duration::from_millis(60));
+		session.on_tick();
+		assert_eq!(session.state, SessionState::Finished);
+		assert_eq!(session.input, vec!["hi", "there"]);
+
+		let stats = SessionStats::from(&session);
+		assert_eq!(stats.acc, None);
+		assert_eq!(stats.char_total, 8);
+		assert_eq!(stats.char_corr, stats.char_total);
+
+		// without an idle limit it only ends when asked to
+		let mut settings = Settings::default();
+		settings.mode = ModeName::Zen;
+		let mut app = App::from(settings);
+		app.on_enter();
+		app.on_key('a');
+		app.on_tick();
+		assert_eq!(app.state, AppState::Typing);
+		app.on_finish();
+		assert_eq!(app.state, AppState::Stats);
+		assert_eq!(app.active_stats.char_total, 1);
+	}
+
+	#[test]
+	fn test_code() {
+		let type_code = |session: &mut Session, text: &str| for c in text.chars() {
+			match c {
+				' ' => session.on_space(),
+				'\n' => session.on_enter(),
+				c => session.on_char(c),
+			}
+		};
+		let code = CodeGenerator::from("test", "if x {\n  y\n\n}").unwrap();
+
+		let mut session = Session::code(code.clone(), false, None);
+		assert!(session.is_code());
+		type_code(&mut session, "if x {\n  y");
+		assert_eq!(session.input, vec!["if", "x", "{", "  y"]);
+		assert!(session.keystrokes.iter().all(|k| k.correct));
+
+		// a space can't end a line, nor Enter a word that isn't done
+		session.on_space();
+		assert!(!session.keystrokes.last().unwrap().correct);
+		assert_eq!(session.keystrokes.last().unwrap().expected, Some('\n'));
+		session.on_del();
+		session.on_del();
+		assert_eq!(session.input, vec!["if", "x", "{", "  "]);
+		type_code(&mut session, "\n");
+		assert_eq!(session.input, vec!["if", "x", "{", "  ", ""]);
+		assert!(!session.keystrokes.last().unwrap().correct);
+
+		// with the indentation skipped, each line starts indented
+		let mut session = Session::code(code, true, None);
+		type_code(&mut session, "if x {\n");
+		assert_eq!(session.input, vec!["if", "x", "{", "  "]);
+		session.on_del();
+		assert_eq!(session.input, vec!["if", "x", "{"]);
+		type_code(&mut session, "\ny\n\n}");
+		assert_eq!(session.state, SessionState::Finished);
+		assert!(session.keystrokes.iter().filter(|k| k.action != KeyAction::Delete).all(|k| k.correct));
+
+		let stats = SessionStats::from(&session);
+		assert_eq!(stats.acc, Some(1.0));
+	}
+
+	#[test]
+	fn test_replay_screens() {
+		let mut app = custom_app("ab cd");
+		app.on_enter();
+		for c in "ab cd".chars() {
+			if c == ' ' { app.on_space() } else { app.on_key(c) }
+		}
+		assert_eq!(app.state, AppState::Stats);
+
+		// straight from the stats screen, and back to it
+		app.on_key('p');
+		assert_eq!(app.state, AppState::Replay);
+		let replay = app.replay.as_mut().unwrap();
+		assert_eq!(replay.len(), 5);
+		replay.step(true);
+		app.on_right();
+		assert_eq!(app.replay.as_ref().unwrap().session.input, vec!["ab"]);
+		app.on_esc();
+		assert_eq!(app.state, AppState::Stats);
+		assert!(app.replay.is_none());
+
+		// or from the list of saved tests
+		app.on_enter();
+		app.on_key('p');
+		assert_eq!(app.state, AppState::Replays);
+		assert_eq!(app.replayable().len(), 1);
+		app.on_down();
+		assert_eq!(app.replay_item, 0);
+		app.on_enter();
+		assert_eq!(app.state, AppState::Replay);
+		app.on_esc();
+		assert_eq!(app.state, AppState::Replays);
+		app.on_esc();
+		assert_eq!(app.state, AppState::Menu);
+	}
+
+	#[test]
+	fn test_race() {
+		let mut settings = Settings::default();
+		settings.words = 10;
+		let mut host = App::from(settings);
+		let host_race = crate::race::Host::bind("127.0.0.1:0", "host").unwrap();
+		let addr = host_race.local_addr().unwrap().to_string();
+		host.race = Some(Race::Host(host_race));
+		host.state = AppState::Lobby;
+		let mut guest = App::from(Settings::default());
+		guest.race = Some(Race::Client(crate::race::Client::connect(&addr, "guest").unwrap()));
+		guest.state = AppState::Lobby;
+
+		wait_until(|| {
+			host.on_tick();
+			host.race.as_ref().unwrap().racers().len() == 2
+		});
+		host.on_enter();
+		assert_eq!(host.state, AppState::Typing);
+		// nobody can type during the countdown, or restart the race
+		host.on_key('a');
+		assert!(host.active_session.keystrokes.is_empty());
+		host.on_repeat();
+		host.on_restart();
+		assert!(!host.restart_pending);
+
+		wait_until(|| {
+			guest.on_tick();
+			guest.state == AppState::Typing
+		});
+		assert!(guest.countdown().is_some());
+		let words: Vec<String> = (0..10)
+			.map(|i| host.active_session.target_word(i).unwrap())
+			.collect();
+		assert_eq!((0..10).map(|i| guest.active_session.target_word(i).unwrap()).collect::<Vec<_>>(), words);
+
+		guest.race_start = Some(Instant::now());
+		for c in words.join(" ").chars() {
+			if c == ' ' { guest.on_space() } else { guest.on_key(c) }
+		}
+		assert_eq!(guest.state, AppState::Stats);
+		wait_until(|| {
+			host.on_tick();
+			host.race.as_ref().unwrap().racers()[1].place == Some(1)
+		});
+		assert!(host.race.as_ref().unwrap().racers()[1].progress.finished);
+
+		// back to the lobby to wait for the next one
+		guest.on_enter();
+		assert_eq!(guest.state, AppState::Lobby);
+		guest.on_enter();
+		assert_eq!(guest.state, AppState::Lobby);
+		guest.on_esc();
+		assert_eq!(guest.state, AppState::Menu);
+	}
+
+	#[test]
+	fn test_history_screen() {
+		let mut app = custom_app("ab");
+		for _ in 0..2 {
+			app.on_enter();
+			for c in "ab".chars() {
+				app.on_key(c);
+			}
+			assert_eq!(app.state, AppState::Stats);
+			app.on_enter();
+		}
+		assert!(app.history.entries().iter().all(|entry| entry.has_full_stats()));
+
+		app.on_key('r');
+		assert_eq!(app.state, AppState::History);
+		// sort, then mode
+		app.on_right();
+		assert_eq!(app.history_filter.sort, crate::history::SortBy::Oldest);
+		app.on_key('j');
+		app.on_right();
+		assert_eq!(app.history_filter.mode, Some(ModeName::Custom));
+		app.on_right();
+		assert_eq!(app.history_filter.mode, None);
+
+		// there's no length to pick without a mode
+		app.on_down();
+		app.on_right();
+		assert_eq!(app.history_filter.length, None);
+		app.on_up();
+		app.on_left();
+		app.on_down();
+		app.on_right();
+		assert_eq!(app.history_filter.length, Some(TestMode::Custom(1)));
+
+		app.on_down();
+		app.on_down();
+		app.on_right();
+		assert_eq!(app.history_filter.days, Some(1));
+
+		// on down past the filters into the results, and back up
+		app.on_down();
+		assert_eq!(app.result_item, Some(0));
+		app.on_down();
+		app.on_down();
+		assert_eq!(app.result_item, Some(1));
+		app.on_right();
+		assert_eq!(app.history_filter.days, Some(1));
+		app.on_enter();
+		assert_eq!(app.state, AppState::Replay);
+		app.on_esc();
+		assert_eq!(app.state, AppState::History);
+		app.on_up();
+		app.on_up();
+		assert_eq!((app.result_item, app.filter_item), (None, FilterItem::ALL.len() - 1));
+
+		app.on_esc();
+		assert_eq!(app.state, AppState::Menu);
+	}
+
 	// TODO tests for wpm, wpm_raw
 }
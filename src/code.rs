@@ -0,0 +1,160 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand::prelude::*;
+
+use crate::app::WordGenerator;
+
+// snippets for each language, separated by lines of "---"
+static SNIPPETS: [(&str, &str); 4] = [
+	("rust", include_str!("snippets/rust.txt")),
+	("python", include_str!("snippets/python.txt")),
+	("javascript", include_str!("snippets/javascript.txt")),
+	("go", include_str!("snippets/go.txt")),
+];
+
+pub const DEFAULT_CODE_LANGUAGE: &str = "rust";
+
+/// Tabs in indentation are typed as this many spaces, so the Tab key is
+/// still free to restart
+const TAB_WIDTH: usize = 4;
+
+/// Languages there are bundled snippets for
+pub fn languages() -> Vec<&'static str> {
+	SNIPPETS.iter().map(|(language, _)| *language).collect()
+}
+
+/// Source code split into words for typing. A line's indentation is the
+/// start of its first word, a blank line is an empty word, and spaces
+/// within a line are collapsed into one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeGenerator {
+	/// the file or language the code came from
+	pub name:	String,
+	words:		Vec<String>,
+	/// whether a line break follows each word
+	line_ends:	Vec<bool>,
+}
+
+impl CodeGenerator {
+	/// None if there's no code in `text`
+	pub fn from(name: &str, text: &str) -> Option<Self> {
+		let mut words = vec![];
+		let mut line_ends = vec![];
+
+		for line in text.lines() {
+			let code = line.trim_start();
+			let indent: usize = line[..line.len() - code.len()].chars()
+				.map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+				.sum();
+
+			let mut tokens = code.split_whitespace();
+			match tokens.next() {
+				Some(first) => {
+					words.push(" ".repeat(indent) + first);
+					line_ends.push(false);
+					for token in tokens {
+						words.push(token.to_string());
+						line_ends.push(false);
+					}
+				}
+				// blank lines before any code don't need typing
+				None if words.is_empty() => continue,
+				None => {
+					words.push(String::new());
+					line_ends.push(false);
+				}
+			}
+			if let Some(end) = line_ends.last_mut() {
+				*end = true;
+			}
+		}
+
+		// nor do blank lines after it
+		while words.last().is_some_and(|word| word.is_empty()) {
+			words.pop();
+			line_ends.pop();
+		}
+		if let Some(end) = line_ends.last_mut() {
+			*end = false;
+		}
+
+		(!words.is_empty()).then(|| Self { name: name.to_string(), words, line_ends })
+	}
+
+	pub fn from_file(path: &Path) -> io::Result<Self> {
+		let contents = fs::read_to_string(path)
+			.map_err(|e| io::Error::new(e.kind(),
+				format!("couldn't read source file '{}': {}", path.display(), e)))?;
+
+		let name = path.file_name()
+			.map_or("source".to_string(), |name| name.to_string_lossy().into_owned());
+
+		Self::from(&name, &contents)
+			.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+				format!("source file '{}' doesn't contain any code", path.display())))
+	}
+
+	/// A bundled snippet in `language`, None if there are none in it
+	pub fn snippet(language: &str, rng: &mut impl Rng) -> Option<Self> {
+		let (_, snippets) = SNIPPETS.iter().find(|(name, _)| *name == language)?;
+		let snippet = snippets.split("\n---\n").choose(rng)?;
+		Self::from(language, snippet)
+	}
+}
+
+/// The spaces `word` starts with: its indentation, if it starts a line
+pub fn indentation(word: &str) -> &str {
+	&word[..word.len() - word.trim_start_matches(' ').len()]
+}
+
+impl WordGenerator for CodeGenerator {
+	fn get_word_at_frozen(&self, index: usize) -> Option<String> {
+		self.words.get(index).cloned()
+	}
+
+	fn get_word_at(&mut self, index: usize) -> Option<String> {
+		self.get_word_at_frozen(index)
+	}
+
+	fn len(&self) -> Option<usize> {
+		Some(self.words.len())
+	}
+
+	fn ends_line(&self, index: usize) -> bool {
+		self.line_ends.get(index).copied().unwrap_or(false)
+	}
+
+	fn repeat(&self) -> Box<dyn WordGenerator> {
+		Box::new(self.clone())
+	}
+}
+
+#[cfg(test)]
+mod code_tests {
+	use super::*;
+
+	#[test]
+	fn test_split_code() {
+		let code = CodeGenerator::from("test", "\nfn main() {\n\tlet x  = 1;\n\n    x\n}\n\n").unwrap();
+		assert_eq!(code.words, vec!["fn", "main()", "{", "    let", "x", "=", "1;", "", "    x", "}"]);
+		let line_ends: Vec<usize> = (0..code.words.len()).filter(|i| code.ends_line(*i)).collect();
+		assert_eq!(line_ends, vec![2, 6, 7, 8]);
+
+		assert_eq!(indentation("    let"), "    ");
+		assert_eq!(indentation("let"), "");
+		assert!(CodeGenerator::from("test", " \n\t\n").is_none());
+	}
+
+	#[test]
+	fn test_snippets() {
+		for (language, snippets) in SNIPPETS {
+			for snippet in snippets.split("\n---\n") {
+				assert!(CodeGenerator::from(language, snippet).is_some(), "{}", language);
+			}
+			assert!(CodeGenerator::snippet(language, &mut rand::rng()).is_some());
+		}
+		assert!(CodeGenerator::snippet("cobol", &mut rand::rng()).is_none());
+	}
+}
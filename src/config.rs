@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::ghost::Pace;
+use crate::paths;
+use crate::quotes::QuoteLength;
+use crate::theme::ThemeConfig;
+use crate::wordlist::Weighting;
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// Which kind of test Enter starts from the menu
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModeName {
+	Words,
+	Time,
+	Quote,
+	/// the text from `--text`
+	Custom,
+	/// free typing with nothing to copy
+	Zen,
+	/// random words that practise the keys the user struggles with
+	Adaptive,
+	/// source code from `--source` or a bundled snippet
+	Code,
+}
+
+impl fmt::Display for ModeName {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			ModeName::Words => "words",
+			ModeName::Time => "time",
+			ModeName::Quote => "quote",
+			ModeName::Custom => "custom",
+			ModeName::Zen => "zen",
+			ModeName::Adaptive => "adaptive",
+			ModeName::Code => "code",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+impl FromStr for ModeName {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"words" => Ok(ModeName::Words),
+			"time" => Ok(ModeName::Time),
+			"quote" => Ok(ModeName::Quote),
+			"custom" => Ok(ModeName::Custom),
+			"zen" => Ok(ModeName::Zen),
+			"adaptive" => Ok(ModeName::Adaptive),
+			"code" => Ok(ModeName::Code),
+			_ => Err(format!("unknown mode '{}' (expected words, time, quote, zen, adaptive or code)", s)),
+		}
+	}
+}
+
+/// Settings from `config.toml`. Anything left out falls back to the
+/// built-in default, and command line flags win over all of it.
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub mode:				Option<ModeName>,
+	/// length of word tests
+	pub words:				Option<usize>,
+	/// length of timed tests, in seconds
+	pub time:				Option<u64>,
+	pub quote:				Option<QuoteLength>,
+	/// seconds without typing before a zen test ends, 0 for never
+	pub zen_idle:			Option<u64>,
+	pub wordlist:			Option<PathBuf>,
+	pub language:			Option<String>,
+	pub punctuation:		bool,
+	pub numbers:			bool,
+	pub punctuation_rate:	Option<f64>,
+	pub numbers_rate:		Option<f64>,
+	/// language of the bundled snippets code tests use
+	pub code_language:		Option<String>,
+	/// start each line of code already indented
+	pub skip_indent:		bool,
+	/// how random words are drawn: uniform, frequency or zipf
+	pub weighting:			Option<Weighting>,
+	/// how many words have to pass before one can come up again
+	pub no_repeat:			Option<usize>,
+	/// how fast the ghost caret goes
+	pub pace:				Option<Pace>,
+	pub live_stats:			bool,
+	/// how often the screen redraws without input, in milliseconds
+	pub refresh_ms:			Option<u64>,
+	/// name of a built-in theme or one from `themes`
+	pub theme:				Option<String>,
+	/// user defined themes, by name
+	pub themes:				HashMap<String, ThemeConfig>,
+	pub keys:				KeyBindings,
+}
+
+impl Config {
+	/// Reads `config.toml` from the user's config directory. A missing
+	/// file is the same as an empty one.
+	pub fn load() -> io::Result<Self> {
+		match paths::config_dir() {
+			Some(dir) => Self::load_from(&dir.join(CONFIG_FILE)),
+			None => Ok(Self::default()),
+		}
+	}
+
+	pub fn load_from(path: &Path) -> io::Result<Self> {
+		let contents = match fs::read_to_string(path) {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+			Err(e) => return Err(e),
+		};
+
+		toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+			format!("invalid config file '{}': {}", path.display(), e)))
+	}
+}
+
+/// Keys for actions outside of typing itself
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+	/// pressed before Enter to restart with new words
+	pub restart:	KeyBinding,
+	/// restart on the same words
+	pub repeat:		KeyBinding,
+	/// leave a test or the stats screen
+	pub menu:		KeyBinding,
+	pub quit:		KeyBinding,
+	/// end a zen test
+	pub finish:		KeyBinding,
+}
+
+impl Default for KeyBindings {
+	fn default() -> Self {
+		Self {
+			restart: KeyBinding::new(KeyCode::Tab, KeyModifiers::NONE),
+			repeat: KeyBinding::new(KeyCode::Char('r'), KeyModifiers::CONTROL),
+			menu: KeyBinding::new(KeyCode::Esc, KeyModifiers::NONE),
+			quit: KeyBinding::new(KeyCode::Char('q'), KeyModifiers::CONTROL),
+			finish: KeyBinding::new(KeyCode::Char('d'), KeyModifiers::CONTROL),
+		}
+	}
+}
+
+/// A key plus modifiers, written like "ctrl+r", "tab" or "f5" in the config
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub struct KeyBinding {
+	pub code:		KeyCode,
+	pub modifiers:	KeyModifiers,
+}
+
+impl KeyBinding {
+	pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+		Self { code, modifiers }
+	}
+
+	pub fn matches(&self, event: &KeyEvent) -> bool {
+		// shift is already part of the character itself
+		let mut modifiers = event.modifiers;
+		if let KeyCode::Char(_) = event.code {
+			modifiers.remove(KeyModifiers::SHIFT);
+		}
+
+		event.code == self.code && modifiers == self.modifiers
+	}
+}
+
+impl TryFrom<String> for KeyBinding {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		let lower = s.to_lowercase();
+		let mut parts: Vec<&str> = lower.split('+').collect();
+		// "ctrl++" binds the plus key
+		if lower.ends_with("++") {
+			parts.truncate(parts.len() - 2);
+			parts.push("+");
+		}
+		let key = parts.pop().filter(|k| !k.is_empty())
+			.ok_or_else(|| format!("no key in binding '{}'", s))?;
+
+		let mut modifiers = KeyModifiers::NONE;
+		for part in parts {
+			modifiers |= match part {
+				"ctrl" | "control" => KeyModifiers::CONTROL,
+				"alt" => KeyModifiers::ALT,
+				"shift" => KeyModifiers::SHIFT,
+				_ => return Err(format!("unknown modifier '{}' in binding '{}'", part, s)),
+			};
+		}
+
+		let code = match key {
+			"tab" => KeyCode::Tab,
+			"esc" | "escape" => KeyCode::Esc,
+			"enter" | "return" => KeyCode::Enter,
+			"space" => KeyCode::Char(' '),
+			"backspace" => KeyCode::Backspace,
+			"delete" | "del" => KeyCode::Delete,
+			"insert" => KeyCode::Insert,
+			"home" => KeyCode::Home,
+			"end" => KeyCode::End,
+			"pageup" => KeyCode::PageUp,
+			"pagedown" => KeyCode::PageDown,
+			"up" => KeyCode::Up,
+			"down" => KeyCode::Down,
+			"left" => KeyCode::Left,
+			"right" => KeyCode::Right,
+			_ => {
+				let mut chars = key.chars();
+				match (chars.next(), chars.next()) {
+					(Some(c), None) => KeyCode::Char(c),
+					_ => match key.strip_prefix('f').and_then(|n| n.parse().ok()) {
+						Some(n) => KeyCode::F(n),
+						None => return Err(format!("unknown key '{}' in binding '{}'", key, s)),
+					},
+				}
+			},
+		};
+
+		// crossterm reports shift+tab as its own key
+		if code == KeyCode::Tab && modifiers.contains(KeyModifiers::SHIFT) {
+			return Ok(Self::new(KeyCode::BackTab, modifiers));
+		}
+
+		Ok(Self::new(code, modifiers))
+	}
+}
+
+impl fmt::Display for KeyBinding {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.modifiers.contains(KeyModifiers::CONTROL) {
+			write!(f, "ctrl+")?;
+		}
+		if self.modifiers.contains(KeyModifiers::ALT) {
+			write!(f, "alt+")?;
+		}
+		if self.modifiers.contains(KeyModifiers::SHIFT) {
+			write!(f, "shift+")?;
+		}
+
+		match self.code {
+			KeyCode::BackTab => write!(f, "tab"),
+			KeyCode::Char(' ') => write!(f, "space"),
+			KeyCode::Char(c) => write!(f, "{}", c),
+			KeyCode::F(n) => write!(f, "f{}", n),
+			code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod config_tests {
+	use super::*;
+	use ratatui::style::Color;
+
+	#[test]
+	fn test_parse_config() {
+		let config: Config = toml::from_str(r##"
+			mode = "time"
+			time = 60
+			language = "german"
+			punctuation = true
+			refresh_ms = 100
+			pace = "90 wpm"
+
+			theme = "mine"
+
+			[themes.mine]
+			base = "light"
+			correct = { fg = "blue" }
+			caret = { bg = "#ff8800", bold = true }
+
+			[keys]
+			restart = "f5"
+			quit = "ctrl+x"
+		"##).unwrap();
+
+		assert_eq!(config.mode, Some(ModeName::Time));
+		assert_eq!(config.time, Some(60));
+		assert_eq!(config.words, None);
+		assert_eq!(config.language.as_deref(), Some("german"));
+		assert!(config.punctuation);
+		assert!(!config.numbers);
+		assert_eq!(config.refresh_ms, Some(100));
+		assert_eq!(config.pace, Some(Pace::Wpm(90)));
+
+		assert_eq!(config.theme.as_deref(), Some("mine"));
+		let mine = &config.themes["mine"];
+		assert_eq!(mine.base.as_deref(), Some("light"));
+		assert_eq!(mine.correct.unwrap().fg, Some(Color::Blue));
+		assert_eq!(mine.caret.unwrap().bg, Some(Color::Rgb(0xff, 0x88, 0x00)));
+		assert_eq!(mine.caret.unwrap().bold, Some(true));
+		assert_eq!(mine.incorrect, None);
+
+		assert_eq!(config.keys.restart, KeyBinding::new(KeyCode::F(5), KeyModifiers::NONE));
+		assert_eq!(config.keys.quit, KeyBinding::new(KeyCode::Char('x'), KeyModifiers::CONTROL));
+		assert_eq!(config.keys.repeat, KeyBindings::default().repeat);
+
+		assert!(toml::from_str::<Config>("colour = \"red\"").is_err());
+		assert!(toml::from_str::<Config>("[keys]\nquit = \"hyper+q\"").is_err());
+		assert!(toml::from_str::<Config>("[themes.x]\ncorrect = { colour = \"red\" }").is_err());
+	}
+
+	#[test]
+	fn test_key_binding() {
+		let ctrl_r = KeyBinding::try_from("Ctrl+R".to_string()).unwrap();
+		assert!(ctrl_r.matches(&KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL)));
+		assert!(!ctrl_r.matches(&KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE)));
+		assert_eq!(ctrl_r.to_string(), "ctrl+r");
+
+		let shift_tab = KeyBinding::try_from("shift+tab".to_string()).unwrap();
+		assert!(shift_tab.matches(&KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT)));
+		assert_eq!(shift_tab.to_string(), "shift+tab");
+
+		let plus = KeyBinding::try_from("ctrl++".to_string()).unwrap();
+		assert_eq!(plus, KeyBinding::new(KeyCode::Char('+'), KeyModifiers::CONTROL));
+		assert_eq!(KeyBindings::default().menu.to_string(), "esc");
+	}
+}
@@ -0,0 +1,169 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::app::{Keystroke, SessionStats};
+use crate::history::HistoryEntry;
+
+/// How `--json` and `--csv` write out the tests taken
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+	/// an object per line
+	Json,
+	/// a header, then a row per test
+	Csv,
+}
+
+/// A finished test as it's written out for other programs
+#[derive(Serialize, Clone, Debug)]
+pub struct TestResult {
+	/// e.g. "time 30s"
+	pub mode:		String,
+	pub word_list:	Option<String>,
+	/// the test code, for tests that have one
+	pub code:		Option<String>,
+	/// seconds since the unix epoch
+	pub timestamp:	u64,
+	#[serde(flatten)]
+	pub stats:		SessionStats,
+	/// only written when asked for
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub keystrokes:	Option<Vec<Keystroke>>,
+}
+
+impl TestResult {
+	pub fn from(entry: &HistoryEntry, code: Option<String>) -> Self {
+		Self {
+			mode: entry.mode.to_string(),
+			word_list: entry.word_list.clone(),
+			code,
+			timestamp: entry.timestamp,
+			stats: entry.stats.clone(),
+			keystrokes: Some(entry.keystrokes.clone()),
+		}
+	}
+}
+
+// the per second timeline doesn't fit in a row, so it's left out
+const CSV_HEADER: [&str; 15] = ["mode", "word_list", "code", "timestamp", "wpm", "wpm_raw", "acc",
+	"consistency", "burst_avg", "burst_max", "word_corr", "word_total", "char_corr", "char_total",
+	"duration_s"];
+
+/// Writes `results` out in `format`, with or without their keystrokes
+pub fn write(out: &mut impl Write, mut results: Vec<TestResult>, format: Format, keystrokes: bool)
+	-> io::Result<()> {
+	if !keystrokes {
+		for result in &mut results {
+			result.keystrokes = None;
+		}
+	}
+
+	match format {
+		Format::Json => for result in &results {
+			writeln!(out, "{}", serde_json::to_string(result)?)?;
+		},
+		Format::Csv => {
+			let mut header = CSV_HEADER.join(",");
+			if keystrokes {
+				header += ",keystrokes";
+			}
+			writeln!(out, "{}", header)?;
+			for result in &results {
+				writeln!(out, "{}", csv_row(result)?)?;
+			}
+		},
+	}
+	out.flush()
+}
+
+fn csv_row(result: &TestResult) -> io::Result<String> {
+	let stats = &result.stats;
+	let mut fields = vec![
+		csv_field(&result.mode),
+		csv_field(result.word_list.as_deref().unwrap_or_default()),
+		csv_field(result.code.as_deref().unwrap_or_default()),
+		result.timestamp.to_string(),
+		stats.wpm.to_string(),
+		stats.wpm_raw.to_string(),
+		stats.acc.map(|acc| acc.to_string()).unwrap_or_default(),
+		stats.consistency.to_string(),
+		stats.burst_avg.to_string(),
+		stats.burst_max.to_string(),
+		stats.word_corr.to_string(),
+		stats.word_total.to_string(),
+		stats.char_corr.to_string(),
+		stats.char_total.to_string(),
+		stats.duration_s.to_string(),
+	];
+	// the keystrokes go in a single field, as JSON
+	if let Some(keystrokes) = &result.keystrokes {
+		fields.push(csv_field(&serde_json::to_string(keystrokes)?));
+	}
+	Ok(fields.join(","))
+}
+
+// quoted when it has anything that would break up the row, with quotes
+// inside doubled
+fn csv_field(s: &str) -> String {
+	if s.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", s.replace('"', "\"\""))
+	} else {
+		s.to_string()
+	}
+}
+
+#[cfg(test)]
+mod export_tests {
+	use super::*;
+	use crate::app::{KeyAction, Session};
+
+	fn result() -> TestResult {
+		let mut session = Session::from("ab".to_string());
+		session.on_char('a');
+		let stats = SessionStats { wpm: 72.5, acc: Some(0.9), word_total: 1, ..SessionStats::default() };
+		let mut entry = HistoryEntry::new(&session, &stats);
+		entry.timestamp = 1710006300;
+		TestResult::from(&entry, None)
+	}
+
+	#[test]
+	fn test_json() {
+		let mut out = vec![];
+		write(&mut out, vec![result(), result()], Format::Json, false).unwrap();
+		let out = String::from_utf8(out).unwrap();
+		let lines: Vec<&str> = out.lines().collect();
+		assert_eq!(lines.len(), 2);
+
+		let json: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+		assert_eq!(json["mode"], "custom 1");
+		assert_eq!(json["wpm"], 72.5);
+		assert_eq!(json["timestamp"], 1710006300);
+		assert!(json.get("keystrokes").is_none());
+
+		let mut out = vec![];
+		write(&mut out, vec![result()], Format::Json, true).unwrap();
+		let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+		let keystrokes: Vec<Keystroke> = serde_json::from_value(json["keystrokes"].clone()).unwrap();
+		assert_eq!(keystrokes[0].action, KeyAction::Char('a'));
+	}
+
+	#[test]
+	fn test_csv() {
+		let mut out = vec![];
+		write(&mut out, vec![result()], Format::Csv, false).unwrap();
+		assert_eq!(String::from_utf8(out).unwrap(), "mode,word_list,code,timestamp,wpm,wpm_raw,acc,\
+			consistency,burst_avg,burst_max,word_corr,word_total,char_corr,char_total,duration_s\n\
+			custom 1,,,1710006300,72.5,0,0.9,0,0,0,0,1,0,0,0\n");
+
+		let mut out = vec![];
+		write(&mut out, vec![result()], Format::Csv, true).unwrap();
+		let out = String::from_utf8(out).unwrap();
+		assert!(out.lines().next().unwrap().ends_with(",duration_s,keystrokes"));
+		assert!(out.lines().nth(1).unwrap().ends_with(",\"[{\"\"time_ms\"\":0,\"\"word\"\":0,\"\"action\"\":{\"\"char\"\":\"\"a\"\"},\
+			\"\"expected\"\":\"\"a\"\",\"\"correct\"\":true}]\""));
+
+		assert_eq!(csv_field("a,b"), "\"a,b\"");
+		assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+		assert_eq!(csv_field("plain"), "plain");
+	}
+}
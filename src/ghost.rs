@@ -0,0 +1,263 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::{Session, SessionStats};
+use crate::code;
+use crate::history::{History, HistoryEntry};
+use crate::replay::Replay;
+
+/// What the menu offers for the ghost
+pub const PACES: [Pace; 8] = [
+	Pace::Off,
+	Pace::Best,
+	Pace::Average,
+	Pace::Replay,
+	Pace::Wpm(40),
+	Pace::Wpm(60),
+	Pace::Wpm(80),
+	Pace::Wpm(100),
+];
+
+/// How fast the ghost caret moves through a test
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Pace {
+	/// no ghost
+	#[default]
+	Off,
+	/// a steady speed, in wpm
+	Wpm(u32),
+	/// steadily at the personal best for the test's settings
+	Best,
+	/// steadily at the average for the test's settings
+	Average,
+	/// the personal best attempt at the same words, played back keystroke
+	/// by keystroke, or steadily at the personal best without one
+	Replay,
+}
+
+impl fmt::Display for Pace {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Pace::Off => write!(f, "off"),
+			Pace::Wpm(wpm) => write!(f, "{} wpm", wpm),
+			Pace::Best => write!(f, "best"),
+			Pace::Average => write!(f, "average"),
+			Pace::Replay => write!(f, "replay"),
+		}
+	}
+}
+
+impl FromStr for Pace {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"off" => Ok(Pace::Off),
+			"best" => Ok(Pace::Best),
+			"average" => Ok(Pace::Average),
+			"replay" => Ok(Pace::Replay),
+			_ => s.trim_end_matches("wpm").trim().parse()
+				.ok()
+				.filter(|wpm| *wpm > 0)
+				.map(Pace::Wpm)
+				.ok_or_else(|| format!("unknown pace '{}' (expected off, best, average, replay or a wpm)", s)),
+		}
+	}
+}
+
+impl TryFrom<String> for Pace {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		s.parse()
+	}
+}
+
+impl From<Pace> for String {
+	fn from(pace: Pace) -> Self {
+		pace.to_string()
+	}
+}
+
+/// A second caret to race against. It sets off with the first keystroke.
+pub struct Ghost {
+	pub pace:	Pace,
+	/// how fast it goes, or went in the attempt being replayed
+	pub wpm:	f32,
+	/// the attempt being replayed, for `Pace::Replay`
+	replay:		Option<Replay>,
+}
+
+impl Ghost {
+	/// None when the pace is off, for zen tests, and when there's nothing
+	/// in `history` for the test's settings to set the pace by
+	pub fn new(pace: Pace, session: &Session, history: &History) -> Option<Self> {
+		if session.is_zen() {
+			return None;
+		}
+
+		let like = HistoryEntry::new(session, &SessionStats::default());
+		let results = history.same_config_as(&like);
+		let (wpm, replay) = match pace {
+			Pace::Off => return None,
+			Pace::Wpm(wpm) => (wpm as f32, None),
+			Pace::Best => (results.map(|e| e.stats.wpm).reduce(f32::max)?, None),
+			Pace::Average => {
+				let wpms: Vec<f32> = results.map(|e| e.stats.wpm).collect();
+				if wpms.is_empty() {
+					return None;
+				}
+				(wpms.iter().sum::<f32>() / wpms.len() as f32, None)
+			},
+			Pace::Replay => {
+				// played back over different words the caret would wander
+				// off them, so it only goes with an attempt at these ones
+				let best = results.filter(|e| e.can_replay() && same_words(session, e))
+					.max_by(|a, b| a.stats.wpm.total_cmp(&b.stats.wpm));
+				match best {
+					Some(best) => (best.stats.wpm, Some(Replay::from(best))),
+					None => return Self::new(Pace::Best, session, history),
+				}
+			},
+		};
+
+		Some(Self { pace, wpm, replay })
+	}
+
+	/// The word and character the ghost is on, None before the test starts
+	pub fn position(&mut self, session: &Session) -> Option<(usize, usize)> {
+		let age_s = session.get_age_s()?;
+		self.position_at(session, age_s)
+	}
+
+	fn position_at(&mut self, session: &Session, age_s: f64) -> Option<(usize, usize)> {
+		if let Some(replay) = &mut self.replay {
+			replay.play_until(age_s * 1000.0);
+			let input = &replay.session.input;
+			let last = input.last().map_or(0, |word| word.chars().count());
+			return Some((input.len() - 1, last));
+		}
+
+		// five characters to a word, counting the space (or Enter) after it
+		let mut chars = (self.wpm as f64 * 5.0 * age_s / 60.0) as usize;
+		let words = session.target_words.as_ref()?;
+		let mut position = (0, 0);
+		for (i, word) in (0..).map_while(|i| words.get_word_at_frozen(i).map(|word| (i, word))) {
+			// skipped indentation doesn't take any typing
+			let skipped = match session.skip_indent {
+				true => code::indentation(&word).len(),
+				false => 0,
+			};
+			let len = word.chars().count();
+			if chars <= len - skipped {
+				return Some((i, skipped + chars));
+			}
+			chars -= len - skipped + 1;
+			position = (i, len);
+		}
+		// it's run out of words, so it waits at the end of the last one
+		Some(position)
+	}
+
+	/// Whether the ghost's wpm was beaten, and by how much either way
+	pub fn result(&self, stats: &SessionStats) -> (bool, f32) {
+		(stats.wpm > self.wpm, (stats.wpm - self.wpm).abs())
+	}
+}
+
+// whether `entry` was typed on the words `session` has, including the ones
+// it has yet to draw
+fn same_words(session: &Session, entry: &HistoryEntry) -> bool {
+	let Some(words) = &session.target_words else {
+		return false;
+	};
+	// drawing ahead on a copy leaves the session's own words as they were
+	let mut words = words.repeat();
+	entry.words.iter()
+		.enumerate()
+		.all(|(i, word)| words.get_word_at(i).as_ref() == Some(word))
+}
+
+impl fmt::Display for Ghost {
+	// e.g. "best (85 wpm)"
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.pace {
+			Pace::Wpm(wpm) => write!(f, "{} wpm", wpm),
+			pace => write!(f, "{} ({:.0} wpm)", pace, self.wpm),
+		}
+	}
+}
+
+#[cfg(test)]
+mod ghost_tests {
+	use super::*;
+	use crate::code::CodeGenerator;
+	use crate::replay::replay_tests::typed_entry;
+
+	#[test]
+	fn test_pace() {
+		for pace in PACES {
+			assert_eq!(pace.to_string().parse::<Pace>().unwrap(), pace);
+		}
+		assert_eq!("75".parse::<Pace>().unwrap(), Pace::Wpm(75));
+		assert!("0".parse::<Pace>().is_err());
+		assert!("fast".parse::<Pace>().is_err());
+	}
+
+	#[test]
+	fn test_steady_ghost() {
+		let mut session = Session::from("ab cde f".to_string());
+		let history = History::default();
+		assert!(Ghost::new(Pace::Best, &session, &history).is_none());
+		assert!(Ghost::new(Pace::Off, &session, &history).is_none());
+
+		// a character every 10ms
+		let mut ghost = Ghost::new(Pace::Wpm(1200), &session, &history).unwrap();
+		assert_eq!(ghost.position(&session), None);
+		session.on_char('a');
+		assert!(ghost.position(&session).is_some());
+
+		assert_eq!(ghost.position_at(&session, 0.0), Some((0, 0)));
+		assert_eq!(ghost.position_at(&session, 0.02), Some((0, 2)));
+		// "ab " then "c"
+		assert_eq!(ghost.position_at(&session, 0.04), Some((1, 1)));
+		assert_eq!(ghost.position_at(&session, 1.0), Some((2, 1)));
+
+		// indentation that's skipped is passed straight over
+		let code = CodeGenerator::from("test", "a\n  bc").unwrap();
+		let session = Session::code(code, true, None);
+		assert_eq!(ghost.position_at(&session, 0.03), Some((1, 3)));
+	}
+
+	#[test]
+	fn test_replay_ghost() {
+		let mut history = History::default();
+		let mut entry = typed_entry("ab cd", "ab c");
+		entry.stats.wpm = 50.0;
+		history.record(entry.clone()).unwrap();
+		entry.stats.wpm = 30.0;
+		history.record(entry).unwrap();
+
+		let fresh = Session::from("ab cd".to_string());
+		let mut ghost = Ghost::new(Pace::Replay, &fresh, &history).unwrap();
+		assert_eq!(ghost.wpm, 50.0);
+		assert_eq!(ghost.position_at(&fresh, 0.15), Some((0, 2)));
+		assert_eq!(ghost.position_at(&fresh, 0.35), Some((1, 1)));
+
+		let average = Ghost::new(Pace::Average, &fresh, &history).unwrap();
+		assert_eq!(average.wpm, 40.0);
+		assert_eq!(average.to_string(), "average (40 wpm)");
+		let stats = SessionStats { wpm: 45.0, ..SessionStats::default() };
+		assert_eq!(average.result(&stats), (true, 5.0));
+
+		// the same settings but other words, so there's nothing to replay
+		let other = Session::from("xy zw".to_string());
+		let ghost = Ghost::new(Pace::Replay, &other, &history).unwrap();
+		assert_eq!(ghost.pace, Pace::Best);
+		assert_eq!(ghost.wpm, 50.0);
+		assert!(ghost.replay.is_none());
+	}
+}
@@ -0,0 +1,439 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::{Keystroke, Session, SessionStats, TestMode};
+use crate::config::ModeName;
+use crate::paths;
+
+const HISTORY_FILE: &str = "history.jsonl";
+const DAY_S: u64 = 86400;
+
+/// How many days back the history screen can look, None for all time
+pub const DATE_RANGES: [Option<u64>; 5] = [None, Some(1), Some(7), Some(30), Some(365)];
+pub const SORT_ORDERS: [SortBy; 4] = [SortBy::Newest, SortBy::Oldest, SortBy::Wpm, SortBy::Accuracy];
+// the order modes are offered in, like the menu's
+const MODE_ORDER: [ModeName; 7] = [ModeName::Words, ModeName::Time, ModeName::Quote, ModeName::Adaptive,
+	ModeName::Custom, ModeName::Code, ModeName::Zen];
+
+/// One finished test as it is stored on disk
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+	pub mode:		TestMode,
+	/// None for tests that didn't use random words
+	#[serde(default)]
+	pub word_list:	Option<String>,
+	#[serde(default)]
+	pub punctuation: bool,
+	#[serde(default)]
+	pub numbers:	bool,
+	/// wpm, accuracy and everything else worked out about the test. Results
+	/// saved before the rest was kept only have wpm, raw wpm and accuracy.
+	#[serde(flatten)]
+	pub stats:		SessionStats,
+	/// seconds since the unix epoch
+	pub timestamp:	u64,
+	#[serde(default)]
+	pub keystrokes:	Vec<Keystroke>,
+	/// the target words the test showed, so it can be replayed. Empty for
+	/// zen tests and results saved before replays.
+	#[serde(default)]
+	pub words:		Vec<String>,
+	/// indexes of the words a line break follows, in code tests
+	#[serde(default)]
+	pub line_ends:	Vec<usize>,
+	#[serde(default)]
+	pub skip_indent: bool,
+}
+
+impl HistoryEntry {
+	pub fn new(session: &Session, stats: &SessionStats) -> Self {
+		let timestamp = now();
+		let words = session.shown_words();
+		let line_ends = (0..words.len())
+			.filter(|i| session.ends_line(*i))
+			.collect();
+
+		Self {
+			mode: session.mode.clone(),
+			word_list: session.word_list.clone(),
+			punctuation: session.modifiers.punctuation,
+			numbers: session.modifiers.numbers,
+			stats: stats.clone(),
+			timestamp,
+			keystrokes: session.keystrokes.clone(),
+			words,
+			line_ends,
+			skip_indent: session.skip_indent,
+		}
+	}
+
+	/// Whether the result has more than wpm, raw wpm and accuracy saved
+	pub fn has_full_stats(&self) -> bool {
+		self.stats.duration_s > 0.0
+	}
+
+	/// Whether there's enough saved to play the test back
+	pub fn can_replay(&self) -> bool {
+		!self.keystrokes.is_empty() && (self.mode == TestMode::Zen || !self.words.is_empty())
+	}
+
+	/// Whether both tests were taken with exactly the same settings
+	pub fn same_config(&self, other: &HistoryEntry) -> bool {
+		self.mode == other.mode
+			&& self.word_list == other.word_list
+			&& self.punctuation == other.punctuation
+			&& self.numbers == other.numbers
+	}
+}
+
+/// Every saved result, one JSON object per line. A history without a path
+/// only lives in memory.
+#[derive(Default)]
+pub struct History {
+	path:		Option<PathBuf>,
+	entries:	Vec<HistoryEntry>,
+}
+
+impl History {
+	/// Loads the history from the user's data directory
+	pub fn load() -> io::Result<Self> {
+		match paths::data_dir() {
+			Some(dir) => Self::load_from(dir.join(HISTORY_FILE)),
+			None => Ok(Self::default()),
+		}
+	}
+
+	pub fn load_from(path: PathBuf) -> io::Result<Self> {
+		let contents = match fs::read_to_string(&path) {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(e),
+		};
+
+		// a damaged line shouldn't cost the user the rest of their history
+		let entries = contents.lines()
+			.filter_map(|line| serde_json::from_str(line).ok())
+			.collect();
+
+		Ok(Self { path: Some(path), entries })
+	}
+
+	/// Every result, oldest first
+	pub fn entries(&self) -> &[HistoryEntry] {
+		&self.entries
+	}
+
+	/// Every result taken with the same settings as `like`, oldest first
+	pub fn same_config_as(&self, like: &HistoryEntry) -> impl Iterator<Item = &HistoryEntry> {
+		self.entries.iter().filter(|e| e.same_config(like))
+	}
+
+	/// Best result (by wpm) taken with the same settings as `like`
+	pub fn personal_best(&self, like: &HistoryEntry) -> Option<&HistoryEntry> {
+		self.same_config_as(like)
+			.max_by(|a, b| a.stats.wpm.total_cmp(&b.stats.wpm))
+	}
+
+	/// The results `filter` lets through, in its order. `now` is in
+	/// seconds since the unix epoch.
+	pub fn filtered(&self, filter: &HistoryFilter, now: u64) -> Vec<&HistoryEntry> {
+		let mut entries: Vec<&HistoryEntry> = self.entries.iter()
+			.filter(|entry| filter.matches(entry, now))
+			.collect();
+		match filter.sort {
+			// entries are already oldest first, and stay that way for ties
+			SortBy::Newest => entries.reverse(),
+			SortBy::Oldest => {},
+			SortBy::Wpm => entries.sort_by(|a, b| b.stats.wpm.total_cmp(&a.stats.wpm)),
+			SortBy::Accuracy => entries.sort_by(|a, b| b.stats.acc.unwrap_or(-1.0).total_cmp(&a.stats.acc.unwrap_or(-1.0))),
+		}
+		entries
+	}
+
+	/// Every kind of test there are results for
+	pub fn modes(&self) -> Vec<ModeName> {
+		MODE_ORDER.into_iter()
+			.filter(|mode| self.entries.iter().any(|entry| entry.mode.name() == *mode))
+			.collect()
+	}
+
+	/// Every length of `mode` there are results for, shortest first
+	pub fn lengths(&self, mode: ModeName) -> Vec<TestMode> {
+		let mut lengths: Vec<TestMode> = vec![];
+		for entry in self.entries.iter().filter(|entry| entry.mode.name() == mode) {
+			if !lengths.contains(&entry.mode) {
+				lengths.push(entry.mode.clone());
+			}
+		}
+		// shorter numbers come first, then it's alphabetical
+		lengths.sort_by_key(|length| {
+			let name = length.to_string();
+			(name.len(), name)
+		});
+		lengths
+	}
+
+	/// Every word list there are results for
+	pub fn word_lists(&self) -> Vec<String> {
+		let mut word_lists: Vec<String> = self.entries.iter()
+			.filter_map(|entry| entry.word_list.clone())
+			.collect();
+		word_lists.sort();
+		word_lists.dedup();
+		word_lists
+	}
+
+	/// Adds an entry and appends it to the history file
+	pub fn record(&mut self, entry: HistoryEntry) -> io::Result<()> {
+		if let Some(path) = &self.path {
+			if let Some(dir) = path.parent() {
+				fs::create_dir_all(dir)?;
+			}
+			let mut file = OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(path)?;
+			writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+		}
+
+		self.entries.push(entry);
+		Ok(())
+	}
+}
+
+/// What the history screen lists results by
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortBy {
+	#[default]
+	Newest,
+	Oldest,
+	/// fastest first
+	Wpm,
+	/// most accurate first
+	Accuracy,
+}
+
+impl fmt::Display for SortBy {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			SortBy::Newest => "newest",
+			SortBy::Oldest => "oldest",
+			SortBy::Wpm => "fastest",
+			SortBy::Accuracy => "most accurate",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+/// Which results the history screen lists, and in what order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistoryFilter {
+	pub sort:		SortBy,
+	/// None for every mode
+	pub mode:		Option<ModeName>,
+	/// a mode and length, like time 30s. None for every length.
+	pub length:		Option<TestMode>,
+	/// None for every word list
+	pub word_list:	Option<String>,
+	/// how many days back to look, None for all time
+	pub days:		Option<u64>,
+}
+
+impl HistoryFilter {
+	pub fn matches(&self, entry: &HistoryEntry, now: u64) -> bool {
+		self.mode.is_none_or(|mode| entry.mode.name() == mode)
+			&& self.length.as_ref().is_none_or(|length| entry.mode == *length)
+			&& self.word_list.as_ref().is_none_or(|list| entry.word_list.as_ref() == Some(list))
+			&& self.days.is_none_or(|days| entry.timestamp + days * DAY_S >= now)
+	}
+}
+
+/// The rows of the history screen's filters, top to bottom
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterItem {
+	Sort,
+	Mode,
+	Length,
+	WordList,
+	Dates,
+}
+
+impl FilterItem {
+	pub const ALL: [FilterItem; 5] = [
+		FilterItem::Sort,
+		FilterItem::Mode,
+		FilterItem::Length,
+		FilterItem::WordList,
+		FilterItem::Dates,
+	];
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			FilterItem::Sort => "sort",
+			FilterItem::Mode => "mode",
+			FilterItem::Length => "length",
+			FilterItem::WordList => "word list",
+			FilterItem::Dates => "dates",
+		}
+	}
+
+	/// Whether the row does anything with `filter` as it is. Lengths are
+	/// only picked between once there's a mode.
+	pub fn applies_to(&self, filter: &HistoryFilter) -> bool {
+		*self != FilterItem::Length || filter.mode.is_some()
+	}
+}
+
+/// Averages and bests over a run of results
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+	pub tests:		usize,
+	pub avg_wpm:	f32,
+	pub best_wpm:	f32,
+	/// None when every result was a zen test, which has no accuracy
+	pub avg_acc:	Option<f32>,
+	pub best_acc:	Option<f32>,
+}
+
+impl Summary {
+	/// None without any results
+	pub fn from(entries: &[&HistoryEntry]) -> Option<Self> {
+		if entries.is_empty() {
+			return None;
+		}
+
+		let accs: Vec<f32> = entries.iter().filter_map(|entry| entry.stats.acc).collect();
+		Some(Self {
+			tests: entries.len(),
+			avg_wpm: entries.iter().map(|entry| entry.stats.wpm).sum::<f32>() / entries.len() as f32,
+			best_wpm: entries.iter().map(|entry| entry.stats.wpm).fold(0.0, f32::max),
+			avg_acc: (!accs.is_empty()).then(|| accs.iter().sum::<f32>() / accs.len() as f32),
+			best_acc: accs.into_iter().reduce(f32::max),
+		})
+	}
+}
+
+/// Seconds since the unix epoch
+pub fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod history_tests {
+	use super::*;
+
+	fn entry(mode: TestMode, wpm: f32) -> HistoryEntry {
+		HistoryEntry { 
+			mode, word_list: None, punctuation: false, numbers: false,
+			stats: SessionStats { wpm, wpm_raw: wpm, acc: Some(1.0), ..SessionStats::default() },
+			timestamp: 0, keystrokes: vec![], words: vec![], line_ends: vec![], skip_indent: false,
+		}
+	}
+
+	#[test]
+	fn test_personal_best() {
+		let mut history = History::default();
+		history.record(entry(TestMode::Words(25), 80.0)).unwrap();
+		history.record(entry(TestMode::Words(25), 95.0)).unwrap();
+		history.record(entry(TestMode::Words(50), 120.0)).unwrap();
+		history.record(entry(TestMode::Time(30), 110.0)).unwrap();
+
+		let mut german = entry(TestMode::Words(25), 150.0);
+		german.word_list = Some("german".to_string());
+		history.record(german.clone()).unwrap();
+
+		let mut punctuated = entry(TestMode::Words(25), 140.0);
+		punctuated.punctuation = true;
+		history.record(punctuated.clone()).unwrap();
+
+		assert_eq!(history.personal_best(&entry(TestMode::Words(25), 0.0)).unwrap().stats.wpm, 95.0);
+		assert_eq!(history.personal_best(&german).unwrap().stats.wpm, 150.0);
+		assert_eq!(history.personal_best(&punctuated).unwrap().stats.wpm, 140.0);
+		assert_eq!(history.personal_best(&entry(TestMode::Time(30), 0.0)).unwrap().stats.wpm, 110.0);
+		assert!(history.personal_best(&entry(TestMode::Time(60), 0.0)).is_none());
+	}
+
+	#[test]
+	fn test_filtered() {
+		let mut history = History::default();
+		for (i, (mode, wpm, acc)) in [
+			(TestMode::Words(25), 80.0, Some(0.9)),
+			(TestMode::Time(30), 95.0, Some(1.0)),
+			(TestMode::Words(100), 70.0, Some(0.95)),
+			(TestMode::Zen, 60.0, None),
+			(TestMode::Words(25), 90.0, Some(0.8)),
+		].into_iter().enumerate() {
+			let mut entry = entry(mode, wpm);
+			entry.stats.acc = acc;
+			entry.timestamp = i as u64 * DAY_S;
+			history.record(entry).unwrap();
+		}
+		let now = 4 * DAY_S;
+		let wpms = |filter: &HistoryFilter| -> Vec<f32> {
+			history.filtered(filter, now).iter().map(|e| e.stats.wpm).collect()
+		};
+
+		let mut filter = HistoryFilter::default();
+		assert_eq!(wpms(&filter), [90.0, 60.0, 70.0, 95.0, 80.0]);
+		filter.sort = SortBy::Accuracy;
+		assert_eq!(wpms(&filter), [95.0, 70.0, 80.0, 90.0, 60.0]);
+
+		filter.sort = SortBy::Wpm;
+		filter.mode = Some(ModeName::Words);
+		assert_eq!(wpms(&filter), [90.0, 80.0, 70.0]);
+		filter.length = Some(TestMode::Words(25));
+		assert_eq!(wpms(&filter), [90.0, 80.0]);
+		filter.days = Some(1);
+		assert_eq!(wpms(&filter), [90.0]);
+		filter.word_list = Some("german".to_string());
+		assert!(wpms(&filter).is_empty());
+
+		assert_eq!(history.modes(), [ModeName::Words, ModeName::Time, ModeName::Zen]);
+		assert_eq!(history.lengths(ModeName::Words), [TestMode::Words(25), TestMode::Words(100)]);
+		assert!(history.word_lists().is_empty());
+
+		let all = history.filtered(&HistoryFilter::default(), now);
+		let summary = Summary::from(&all).unwrap();
+		assert_eq!((summary.tests, summary.avg_wpm, summary.best_wpm), (5, 79.0, 95.0));
+		assert_eq!((summary.avg_acc, summary.best_acc), (Some(0.9125), Some(1.0)));
+		assert!(Summary::from(&[]).is_none());
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let path = std::env::temp_dir()
+			.join(format!("term-type-history-{}", std::process::id()))
+			.join(HISTORY_FILE);
+		let _ = fs::remove_file(&path);
+
+		let mut history = History::load_from(path.clone()).unwrap();
+		assert!(history.entries.is_empty());
+		let mut timed = entry(TestMode::Time(15), 70.5);
+		timed.stats.consistency = 0.8;
+		timed.stats.duration_s = 15.0;
+		history.record(timed).unwrap();
+		history.record(entry(TestMode::Custom(9), 40.0)).unwrap();
+
+		let reloaded = History::load_from(path.clone()).unwrap();
+		assert_eq!(reloaded.entries, history.entries);
+		// wpm and the like are saved once, alongside the rest of the stats
+		let line = fs::read_to_string(&path).unwrap();
+		assert_eq!(line.matches("\"wpm\"").count(), 2);
+
+		// results from before the full stats were kept
+		let old: HistoryEntry = serde_json::from_str(
+			r#"{"mode":{"words":25},"wpm":80.0,"wpm_raw":85.0,"acc":0.9,"timestamp":5}"#).unwrap();
+		assert_eq!((old.stats.wpm, old.stats.wpm_raw, old.stats.acc), (80.0, 85.0, Some(0.9)));
+		assert!(!old.has_full_stats());
+		assert!(reloaded.entries[0].has_full_stats());
+
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+	}
+}
@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::{KeyAction, Keystroke};
+use crate::paths;
+
+const KEY_STATS_FILE: &str = "keys.json";
+
+/// How one character has gone whenever the text called for it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct KeyStat {
+	pub hits:			u32,
+	pub misses:			u32,
+	/// sum of the time taken to reach this character from the keystroke
+	/// before, over `timed` presses
+	pub latency_ms:		u64,
+	pub timed:			u32,
+	/// what was typed instead, and how often
+	pub substitutions:	BTreeMap<char, u32>,
+}
+
+impl KeyStat {
+	pub fn presses(&self) -> u32 {
+		self.hits + self.misses
+	}
+
+	pub fn miss_rate(&self) -> f32 {
+		match self.presses() {
+			0 => 0.0,
+			presses => self.misses as f32 / presses as f32,
+		}
+	}
+
+	pub fn avg_latency_ms(&self) -> Option<f32> {
+		(self.timed > 0).then(|| self.latency_ms as f32 / self.timed as f32)
+	}
+
+	/// The `n` most common wrong characters, most common first
+	pub fn top_substitutions(&self, n: usize) -> Vec<char> {
+		let mut substitutions: Vec<(&char, &u32)> = self.substitutions.iter().collect();
+		substitutions.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+		substitutions.into_iter().take(n).map(|(c, _)| *c).collect()
+	}
+
+	fn count(&mut self, expected: char, correct: bool, typed: char, latency_ms: Option<u64>) {
+		if correct {
+			self.hits += 1;
+		} else {
+			self.misses += 1;
+			if typed != expected {
+				*self.substitutions.entry(typed).or_default() += 1;
+			}
+		}
+
+		if let Some(latency_ms) = latency_ms {
+			self.latency_ms += latency_ms;
+			self.timed += 1;
+		}
+	}
+
+	pub fn add(&mut self, other: &KeyStat) {
+		self.hits += other.hits;
+		self.misses += other.misses;
+		self.latency_ms += other.latency_ms;
+		self.timed += other.timed;
+		for (c, count) in &other.substitutions {
+			*self.substitutions.entry(*c).or_default() += count;
+		}
+	}
+}
+
+/// Per character stats, either for one test or every test taken. The
+/// running totals for every test are kept in the data directory.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct KeyStats {
+	#[serde(skip)]
+	path:			Option<PathBuf>,
+	pub keys:		BTreeMap<char, KeyStat>,
+	/// the same, for a character typed straight after another within a
+	/// word, keyed by both characters
+	#[serde(default)]
+	pub bigrams:	BTreeMap<String, KeyStat>,
+}
+
+impl KeyStats {
+	/// Counts every keystroke under the character the text called for at
+	/// the time. Deletes, characters typed past the end of a word and zen
+	/// tests have nothing to count against.
+	pub fn from_keystrokes(keystrokes: &[Keystroke]) -> Self {
+		let mut stats = Self::default();
+		let mut previous_ms = None;
+		// what the key before called for, None after a delete or whitespace
+		let mut previous_expected = None;
+
+		for keystroke in keystrokes {
+			let last_ms = previous_ms.replace(keystroke.time_ms);
+			let last_expected = previous_expected.take();
+			let Some(expected) = keystroke.expected else {
+				continue;
+			};
+			let typed = match keystroke.action {
+				KeyAction::Char(c) => c,
+				KeyAction::Space => ' ',
+				KeyAction::Enter => '\n',
+				KeyAction::Delete => continue,
+			};
+			// the very first key has nothing to be timed from
+			let latency_ms = last_ms.map(|last_ms| keystroke.time_ms.saturating_sub(last_ms));
+
+			stats.keys.entry(expected).or_default()
+				.count(expected, keystroke.correct, typed, latency_ms);
+			if let Some(first) = last_expected && !expected.is_whitespace() {
+				stats.bigrams.entry(format!("{}{}", first, expected)).or_default()
+					.count(expected, keystroke.correct, typed, latency_ms);
+			}
+
+			if !expected.is_whitespace() {
+				previous_expected = Some(expected);
+			}
+		}
+
+		stats
+	}
+
+	/// Loads the totals from the user's data directory
+	pub fn load() -> io::Result<Self> {
+		match paths::data_dir() {
+			Some(dir) => Self::load_from(dir.join(KEY_STATS_FILE)),
+			None => Ok(Self::default()),
+		}
+	}
+
+	/// Empty totals, for when the saved ones can't be read. Those are moved
+	/// to keys.json.bak first, so recording to these doesn't write over them.
+	pub fn reset() -> io::Result<Self> {
+		match paths::data_dir() {
+			Some(dir) => Self::reset_at(dir.join(KEY_STATS_FILE)),
+			None => Ok(Self::default()),
+		}
+	}
+
+	pub fn reset_at(path: PathBuf) -> io::Result<Self> {
+		fs::rename(&path, path.with_extension("json.bak"))?;
+		Ok(Self { path: Some(path), ..Self::default() })
+	}
+
+	pub fn load_from(path: PathBuf) -> io::Result<Self> {
+		let contents = match fs::read_to_string(&path) {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == io::ErrorKind::NotFound =>
+				return Ok(Self { path: Some(path), ..Self::default() }),
+			Err(e) => return Err(e),
+		};
+
+		let stats: Self = serde_json::from_str(&contents)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+				format!("invalid key stats file '{}': {}", path.display(), e)))?;
+		Ok(Self { path: Some(path), ..stats })
+	}
+
+	/// Adds one test's stats to the totals and saves them
+	pub fn record(&mut self, test: &KeyStats) -> io::Result<()> {
+		for (c, stat) in &test.keys {
+			self.keys.entry(*c).or_default().add(stat);
+		}
+		for (bigram, stat) in &test.bigrams {
+			self.bigrams.entry(bigram.clone()).or_default().add(stat);
+		}
+
+		if let Some(path) = &self.path {
+			if let Some(dir) = path.parent() {
+				fs::create_dir_all(dir)?;
+			}
+			fs::write(path, serde_json::to_string(self)?)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod keystats_tests {
+	use super::*;
+
+	fn key(time_ms: u64, action: KeyAction, expected: Option<char>, correct: bool) -> Keystroke {
+		Keystroke { time_ms, word: 0, action, expected, correct }
+	}
+
+	#[test]
+	fn test_from_keystrokes() {
+		let stats = KeyStats::from_keystrokes(&[
+			key(0, KeyAction::Char('t'), Some('t'), true),
+			key(100, KeyAction::Char('r'), Some('e'), false),
+			key(150, KeyAction::Delete, None, false),
+			key(250, KeyAction::Char('e'), Some('e'), true),
+			key(400, KeyAction::Space, Some(' '), true),
+			key(500, KeyAction::Char('w'), Some('e'), false),
+			key(600, KeyAction::Char('r'), Some('e'), false),
+			key(700, KeyAction::Char('x'), None, false),
+		]);
+
+		let t = &stats.keys[&'t'];
+		assert_eq!((t.hits, t.misses, t.avg_latency_ms()), (1, 0, None));
+
+		let e = &stats.keys[&'e'];
+		assert_eq!((e.hits, e.misses), (1, 3));
+		assert_eq!(e.miss_rate(), 0.75);
+		assert_eq!(e.avg_latency_ms(), Some((100.0 + 100.0 + 100.0 + 100.0) / 4.0));
+		assert_eq!(e.top_substitutions(2), vec!['r', 'w']);
+
+		assert_eq!(stats.keys[&' '].hits, 1);
+		assert!(!stats.keys.contains_key(&'x'));
+
+		// "te" and "ee", but nothing across the delete or the space
+		assert_eq!(stats.bigrams.keys().collect::<Vec<_>>(), vec!["ee", "te"]);
+		assert_eq!((stats.bigrams["te"].hits, stats.bigrams["te"].misses), (0, 1));
+		assert_eq!(stats.bigrams["ee"].misses, 1);
+	}
+
+	#[test]
+	fn test_record_round_trip() {
+		let dir = std::env::temp_dir()
+			.join(format!("term-type-keystats-test-{}", std::process::id()));
+		let path = dir.join(KEY_STATS_FILE);
+		let _ = fs::remove_dir_all(&dir);
+
+		let test = KeyStats::from_keystrokes(&[
+			key(0, KeyAction::Char('a'), Some('a'), true),
+			key(90, KeyAction::Char('s'), Some('b'), false),
+		]);
+		let mut totals = KeyStats::load_from(path.clone()).unwrap();
+		totals.record(&test).unwrap();
+		totals.record(&test).unwrap();
+
+		let loaded = KeyStats::load_from(path.clone()).unwrap();
+		assert_eq!(loaded, totals);
+		assert_eq!(loaded.keys[&'a'].hits, 2);
+		assert_eq!(loaded.keys[&'b'].substitutions[&'s'], 2);
+
+		fs::write(&path, "not json").unwrap();
+		let damaged = KeyStats::load_from(path.clone()).unwrap_err();
+		assert_eq!(damaged.kind(), io::ErrorKind::InvalidData);
+
+		// starting over keeps the damaged file around
+		let mut totals = KeyStats::reset_at(path.clone()).unwrap();
+		totals.record(&test).unwrap();
+		assert_eq!(KeyStats::load_from(path).unwrap().keys[&'a'].hits, 1);
+		assert_eq!(fs::read_to_string(dir.join("keys.json.bak")).unwrap(), "not json");
+
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
@@ -1,14 +1,47 @@
-use std::io;
+use std::fs::File;
+use std::io::{self, Stderr, Write};
+use std::path::PathBuf;
 use std::time::{Instant, Duration};
 
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
-use ratatui::DefaultTerminal;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::Terminal;
 
 use argh::FromArgs;
 
+mod adaptive;
 mod app;
+mod code;
+mod config;
+mod export;
+mod ghost;
+mod history;
+mod keystats;
+mod modifiers;
+mod paths;
+mod quotes;
+mod race;
+mod replay;
+mod settings;
+mod share;
+mod theme;
 mod ui;
-use crate::app::App;
+mod wordlist;
+use crate::app::{App, AppState, TestMode};
+use crate::config::{Config, ModeName};
+use crate::export::Format;
+use crate::ghost::Pace;
+use crate::history::History;
+use crate::keystats::KeyStats;
+use crate::modifiers::Modifiers;
+use crate::quotes::QuoteLength;
+use crate::race::{Client, Host, Race, DEFAULT_PORT};
+use crate::settings::Settings;
+use crate::share::TestCode;
+use crate::theme::DEFAULT_THEME;
+use crate::wordlist::{Sampling, Weighting};
 use crate::ui::draw;
 
 /// TermType
@@ -20,37 +53,480 @@ struct Cli {
 	/// number of random words to generate (incompatible with --text)
 	#[argh(option)]
 	with_word_count: Option<usize>,
+	/// length of a timed test in seconds, e.g. 15, 30, 60 or 120
+	/// (incompatible with --text and --with-word-count)
+	#[argh(option)]
+	time: Option<u64>,
+	/// type a bundled quote, of the length last picked in the menu unless
+	/// --quote-length is given (incompatible with the other modes)
+	#[argh(switch)]
+	quote: bool,
+	/// length of the quote: short, medium, long or thicc (implies --quote)
+	#[argh(option)]
+	quote_length: Option<QuoteLength>,
+	/// type freely with no target text, until the finish key (ctrl+d by
+	/// default) is pressed (incompatible with the other modes)
+	#[argh(switch)]
+	zen: bool,
+	/// source file to type, newlines and indentation included
+	/// (incompatible with the other modes)
+	#[argh(option)]
+	source: Option<PathBuf>,
+	/// type a bundled code snippet in the given language: rust, python,
+	/// javascript or go (incompatible with the other modes)
+	#[argh(option)]
+	snippet: Option<String>,
+	/// start each line of code already indented, instead of typing the
+	/// indentation
+	#[argh(switch)]
+	skip_indent: bool,
+	/// type the indentation of each line of code, even if the menu or
+	/// config file skips it
+	#[argh(switch)]
+	no_skip_indent: bool,
+	/// kind of test to start with the menu's settings for it: words, time,
+	/// quote, zen, code or adaptive, which practises the keys you miss most
+	/// or type slowest (incompatible with the other modes)
+	#[argh(option)]
+	mode: Option<ModeName>,
+	/// file to draw random words from, one word per line
+	#[argh(option)]
+	wordlist: Option<PathBuf>,
+	/// name of an installed word list to draw random words from, looked up
+	/// in the term-type data directory (e.g. english-10k, german)
+	#[argh(option)]
+	language: Option<String>,
+	/// add punctuation and capitals to random words
+	#[argh(switch)]
+	punctuation: bool,
+	/// leave punctuation and capitals out, even if the menu or config file
+	/// turns them on
+	#[argh(switch)]
+	no_punctuation: bool,
+	/// mix numbers in with random words
+	#[argh(switch)]
+	numbers: bool,
+	/// leave numbers out, even if the menu or config file turns them on
+	#[argh(switch)]
+	no_numbers: bool,
+	/// chance of a word getting punctuation when --punctuation is on,
+	/// from 0.0 to 1.0 (default 0.25)
+	#[argh(option)]
+	punctuation_rate: Option<f64>,
+	/// chance of a word being a number when --numbers is on, from 0.0 to
+	/// 1.0 (default 0.15)
+	#[argh(option)]
+	numbers_rate: Option<f64>,
+	/// how random words are drawn: uniform (the default), frequency, which
+	/// follows the counts in lists with them, or zipf
+	#[argh(option)]
+	weighting: Option<Weighting>,
+	/// keep a word from coming up again within this many words of itself
+	#[argh(option)]
+	no_repeat: Option<usize>,
+	/// seed for the random words of the first test, to get the same ones
+	/// every time
+	#[argh(option)]
+	seed: Option<u64>,
+	/// a test code from the stats screen, to take a test on the exact same
+	/// words (incompatible with the other modes and with options that
+	/// change the words)
+	#[argh(option)]
+	code: Option<TestCode>,
+	/// race a ghost caret: a wpm to go at, or best, average or replay to
+	/// race your best or average speed for tests with the same settings or
+	/// replay your best attempt at the same words
+	#[argh(option)]
+	pace: Option<Pace>,
+	/// show wpm, accuracy and progress while typing
+	#[argh(switch)]
+	live_stats: bool,
+	/// colour theme: dark, light, high-contrast, colorblind, or one defined
+	/// in the config file
+	#[argh(option)]
+	theme: Option<String>,
+	/// print the results of the tests taken as JSON, an object per line,
+	/// once term-type exits
+	#[argh(switch)]
+	json: bool,
+	/// print the results of the tests taken as CSV, a row per test, once
+	/// term-type exits
+	#[argh(switch)]
+	csv: bool,
+	/// include every keystroke in the --json or --csv results
+	#[argh(switch)]
+	keystrokes: bool,
+	/// file to write the --json or --csv results to, instead of printing
+	/// them
+	#[argh(option)]
+	output: Option<PathBuf>,
+	#[argh(subcommand)]
+	command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+	Host(HostCommand),
+	Join(JoinCommand),
+}
+
+/// Host a race for others on the network to join. Every test started is
+/// raced by everyone who has joined.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "host")]
+struct HostCommand {
+	/// port to listen on (default 7878)
+	#[argh(option, default = "DEFAULT_PORT")]
+	port: u16,
+	/// name to race under (defaults to your user name)
+	#[argh(option)]
+	name: Option<String>,
 }
 
+/// Join a race someone is hosting
+#[derive(FromArgs)]
+#[argh(subcommand, name = "join")]
+struct JoinCommand {
+	/// the host's address, e.g. 192.168.1.20 or 192.168.1.20:7878
+	#[argh(positional)]
+	addr: String,
+	/// name to race under (defaults to your user name)
+	#[argh(option)]
+	name: Option<String>,
+}
+
+// the user name, or "player" when there isn't one
+fn default_name() -> String {
+	std::env::var("USER")
+		.or_else(|_| std::env::var("USERNAME"))
+		.ok()
+		.filter(|name| !name.is_empty())
+		.unwrap_or_else(|| "player".to_string())
+}
+
+const DEFAULT_REFRESH_MS: u64 = 250;
+
 fn main() -> io::Result<()> {
 	let mut cli: Cli = argh::from_env();
 
-    let mut terminal = ratatui::init();
-	let mut app: App;
-	let refresh_wait = Duration::from_millis(250);
-	
-	if let Some(target_text) = cli.text.take() {
-		if cli.with_word_count.is_some() {
-			println!("--text is incompatible with --with-word-count!");
+	let modes_chosen = [
+		cli.text.is_some(),
+		cli.with_word_count.is_some(),
+		cli.time.is_some(),
+		cli.quote || cli.quote_length.is_some(),
+		cli.zen,
+		cli.source.is_some(),
+		cli.snippet.is_some(),
+		cli.mode.is_some(),
+		cli.code.is_some(),
+	];
+	if modes_chosen.iter().filter(|chosen| **chosen).count() > 1 {
+		eprintln!("Only one of --text, --with-word-count, --time, --quote, --zen, --source, --snippet, --mode and --code can be used!");
+		return Ok(());
+	}
+	if (cli.punctuation && cli.no_punctuation) || (cli.numbers && cli.no_numbers)
+		|| (cli.skip_indent && cli.no_skip_indent) {
+		eprintln!("A switch can't be given along with its --no- counterpart!");
+		return Ok(());
+	}
+	if cli.code.is_some() && (cli.wordlist.is_some() || cli.language.is_some()
+		|| cli.punctuation || cli.numbers || cli.no_punctuation || cli.no_numbers || cli.punctuation_rate.is_some() || cli.numbers_rate.is_some()
+		|| cli.weighting.is_some() || cli.no_repeat.is_some() || cli.seed.is_some()) {
+		eprintln!("--code already picks the words, so it can't be used with options that change them!");
+		return Ok(());
+	}
+	if cli.json && cli.csv {
+		eprintln!("--json is incompatible with --csv!");
+		return Ok(());
+	}
+	let format = match (cli.json, cli.csv) {
+		(true, _) => Some(Format::Json),
+		(_, true) => Some(Format::Csv),
+		_ => None,
+	};
+	if format.is_none() && (cli.keystrokes || cli.output.is_some()) {
+		eprintln!("--keystrokes and --output only apply with --json or --csv!");
+		return Ok(());
+	}
+	if cli.mode == Some(ModeName::Custom) {
+		eprintln!("Use --text to start a custom test!");
+		return Ok(());
+	}
+
+	if cli.wordlist.is_some() && cli.language.is_some() {
+		eprintln!("--wordlist is incompatible with --language!");
+		return Ok(());
+	}
+	if (cli.wordlist.is_some() || cli.language.is_some() || cli.punctuation || cli.numbers
+			|| cli.weighting.is_some() || cli.no_repeat.is_some())
+		&& (cli.text.is_some() || cli.quote || cli.quote_length.is_some() || cli.zen || cli.source.is_some() || cli.snippet.is_some()
+			|| matches!(cli.mode, Some(ModeName::Quote | ModeName::Zen | ModeName::Code))) {
+		eprintln!("--wordlist, --language, --punctuation, --numbers, --weighting and --no-repeat only apply to random word tests!");
+		return Ok(());
+	}
+
+	let config = match Config::load() {
+		Ok(config) => config,
+		Err(e) => {
+			eprintln!("Error: {}", e);
+			return Ok(());
+		}
+	};
+	if config.wordlist.is_some() && config.language.is_some() {
+		eprintln!("Error: the config file can't set both wordlist and language");
+		return Ok(());
+	}
+
+	// anything the command line asks for has to exist
+	let cli_picked_files = cli.wordlist.is_some() || cli.language.is_some() || cli.theme.is_some()
+		|| cli.code.is_some() || cli.source.is_some() || cli.snippet.is_some();
+	let saved_settings = match Settings::load(Settings::from_config(&config)) {
+		Ok(settings) => settings,
+		Err(e) => {
+			eprintln!("Couldn't read saved settings: {}", e);
+			return Ok(());
+		}
+	};
+
+	// flags on the command line take priority over the menu and the config
+	// file, but only for this run, so they go on a copy that isn't saved
+	let mut settings = saved_settings.clone();
+	if let Some(text) = cli.text.take() {
+		settings.mode = ModeName::Custom;
+		settings.text = Some(text);
+	} else if let Some(word_count) = cli.with_word_count {
+		settings.mode = ModeName::Words;
+		settings.words = word_count;
+	} else if let Some(seconds) = cli.time {
+		settings.mode = ModeName::Time;
+		settings.time = seconds;
+	} else if cli.quote || cli.quote_length.is_some() {
+		settings.mode = ModeName::Quote;
+		if let Some(length) = cli.quote_length {
+			settings.quote = length;
+		}
+	} else if cli.zen {
+		settings.mode = ModeName::Zen;
+	} else if cli.source.is_some() {
+		settings.mode = ModeName::Code;
+		settings.source = cli.source.take();
+	} else if let Some(language) = cli.snippet.take() {
+		settings.mode = ModeName::Code;
+		settings.code_language = language;
+	} else if let Some(mode) = cli.mode {
+		settings.mode = mode;
+	} else if let Some(code) = cli.code.take() {
+		match code.mode {
+			TestMode::Words(n) => {
+				settings.mode = ModeName::Words;
+				settings.words = n;
+			},
+			TestMode::Time(s) => {
+				settings.mode = ModeName::Time;
+				settings.time = s;
+			},
+			TestMode::Quote(length) => {
+				settings.mode = ModeName::Quote;
+				settings.quote = length;
+			},
+			_ => {},
+		}
+		if code.word_list.is_some() {
+			settings.wordlist = None;
+			settings.language = code.word_list;
+		}
+		settings.punctuation = code.modifiers.punctuation;
+		settings.numbers = code.modifiers.numbers;
+		cli.punctuation_rate = Some(code.modifiers.punctuation_rate);
+		cli.numbers_rate = Some(code.modifiers.numbers_rate);
+		cli.weighting = Some(code.sampling.weighting);
+		cli.no_repeat = Some(code.sampling.no_repeat);
+		cli.seed = Some(code.seed);
+	}
+	if cli.wordlist.is_some() || cli.language.is_some() {
+		settings.wordlist = cli.wordlist.take();
+		settings.language = cli.language.take();
+	}
+	if cli.punctuation || cli.no_punctuation {
+		settings.punctuation = cli.punctuation;
+	}
+	if cli.numbers || cli.no_numbers {
+		settings.numbers = cli.numbers;
+	}
+	if cli.skip_indent || cli.no_skip_indent {
+		settings.skip_indent = cli.skip_indent;
+	}
+	if let Some(pace) = cli.pace {
+		settings.pace = pace;
+	}
+	if let Some(theme) = cli.theme.take() {
+		settings.theme = theme;
+	}
+	cli.punctuation_rate = cli.punctuation_rate.or(config.punctuation_rate);
+	cli.numbers_rate = cli.numbers_rate.or(config.numbers_rate);
+	cli.live_stats |= config.live_stats;
+	let refresh_wait = Duration::from_millis(config.refresh_ms.unwrap_or(DEFAULT_REFRESH_MS));
+
+	let mut app = App::from(settings);
+	app.saved_settings = saved_settings;
+	app.custom_themes = config.themes;
+	if let Err(e) = app.apply_settings() {
+		if cli_picked_files {
+			eprintln!("Error: {}", e);
+			return Ok(());
+		}
+
+		// a list, source file or theme picked in an earlier run may have
+		// been removed since, which shouldn't stop term-type from starting
+		for settings in [&mut app.settings, &mut app.saved_settings] {
+			settings.wordlist = None;
+			settings.language = None;
+			settings.source = None;
+			settings.code_language = code::DEFAULT_CODE_LANGUAGE.to_string();
+			settings.theme = DEFAULT_THEME.to_string();
+		}
+		app.menu_error = Some(e);
+		let _ = app.apply_settings();
+	}
+
+	let mut modifiers = Modifiers::default();
+	for (flag, rate, field) in [
+		("--punctuation-rate", cli.punctuation_rate, &mut modifiers.punctuation_rate),
+		("--numbers-rate", cli.numbers_rate, &mut modifiers.numbers_rate),
+	] {
+		if let Some(rate) = rate {
+			if !(0.0..=1.0).contains(&rate) {
+				eprintln!("{} must be between 0.0 and 1.0!", flag);
+				return Ok(());
+			}
+			*field = rate;
+		}
+	}
+	app.modifiers = modifiers;
+	app.sampling = Sampling {
+		weighting: cli.weighting.or(config.weighting).unwrap_or_default(),
+		no_repeat: cli.no_repeat.or(config.no_repeat).unwrap_or(0),
+	};
+	app.seed = cli.seed;
+	app.show_live_stats = cli.live_stats;
+	app.keys = config.keys;
+
+	match History::load() {
+		Ok(history) => app.history = history,
+		Err(e) => {
+			eprintln!("Couldn't read test history: {}", e);
+			return Ok(());
+		}
+	}
+	match KeyStats::load() {
+		Ok(key_stats) => app.key_stats = key_stats,
+		// damaged key stats are started over rather than keeping term-type
+		// from starting, with the old file kept as keys.json.bak
+		Err(e) if e.kind() == io::ErrorKind::InvalidData => match KeyStats::reset() {
+			Ok(key_stats) => {
+				app.key_stats = key_stats;
+				app.menu_error.get_or_insert(
+					format!("{}, so key stats start over (old ones kept in keys.json.bak)", e));
+			},
+			Err(e) => {
+				eprintln!("Couldn't set damaged key stats aside: {}", e);
+				return Ok(());
+			}
+		},
+		Err(e) => {
+			eprintln!("Couldn't read key stats: {}", e);
 			return Ok(());
 		}
-		app = App::from_str(target_text);
-	} else if let Some(word_count) = cli.with_word_count.take() {
-		app = App::with_word_count(word_count);
+	}
+
+	match cli.command.take() {
+		Some(Command::Host(host)) => {
+			let name = host.name.unwrap_or_else(default_name);
+			match Host::bind(("0.0.0.0", host.port), &name) {
+				Ok(host) => app.race = Some(Race::Host(host)),
+				Err(e) => {
+					eprintln!("Couldn't host on port {}: {}", host.port, e);
+					return Ok(());
+				}
+			}
+		},
+		Some(Command::Join(join)) => {
+			let name = join.name.unwrap_or_else(default_name);
+			match Client::connect(&join.addr, &name) {
+				Ok(client) => app.race = Some(Race::Client(client)),
+				Err(e) => {
+					eprintln!("Couldn't join {}: {}", join.addr, e);
+					return Ok(());
+				}
+			}
+		},
+		None => {},
+	}
+	if app.race.is_some() {
+		app.state = AppState::Lobby;
+	}
+
+	// the file is made up front, so a bad path doesn't cost a test's results
+	let to_stdout = format.is_some() && cli.output.is_none();
+	let mut out: Box<dyn Write> = match &cli.output {
+		Some(path) => match File::create(path) {
+			Ok(file) => Box::new(file),
+			Err(e) => {
+				eprintln!("Couldn't create {}: {}", path.display(), e);
+				return Ok(());
+			}
+		},
+		None => Box::new(io::stdout()),
+	};
+
+	// results printed for a script are kept apart from the tests, which are
+	// drawn on stderr instead
+	let result = if to_stdout {
+		let result = init_stderr()
+			.and_then(|mut terminal| run(&mut app, &mut terminal, refresh_wait));
+		restore_stderr();
+		result
 	} else {
-		app = App::default();
+		let mut terminal = ratatui::init();
+		let result = run(&mut app, &mut terminal, refresh_wait);
+		ratatui::restore();
+		result
+	};
+	result?;
+
+	if let Some(format) = format {
+		let results = std::mem::take(&mut app.results);
+		if let Err(e) = export::write(&mut out, results, format, cli.keystrokes) {
+			eprintln!("Couldn't write results: {}", e);
+		}
 	}
+	Ok(())
+}
+
+// like ratatui::init, but drawing on stderr
+fn init_stderr() -> io::Result<Terminal<CrosstermBackend<Stderr>>> {
+	let hook = std::panic::take_hook();
+	std::panic::set_hook(Box::new(move |info| {
+		restore_stderr();
+		hook(info);
+	}));
+	enable_raw_mode()?;
+	execute!(io::stderr(), EnterAlternateScreen)?;
+	Terminal::new(CrosstermBackend::new(io::stderr()))
+}
 
-	let result = run(&mut app, &mut terminal, refresh_wait);
-	ratatui::restore();
-	result
+fn restore_stderr() {
+	let _ = disable_raw_mode();
+	let _ = execute!(io::stderr(), LeaveAlternateScreen);
 }
 
-fn run(app: &mut App, terminal: &mut DefaultTerminal, refresh_wait: Duration) -> io::Result<()> {
+fn run<B: Backend>(app: &mut App, terminal: &mut Terminal<B>, refresh_wait: Duration) -> io::Result<()> {
 	let mut last_tick = Instant::now();
 	while !app.quit {
 		terminal.draw(|frame| draw(frame, app))?;
 		handle_events(app, refresh_wait, last_tick)?;
+		app.on_tick();
 		last_tick = Instant::now();
 	}
 	Ok(())
@@ -70,6 +546,34 @@ fn handle_events(app: &mut App, refresh_wait: Duration, last_tick: Instant) -> i
 }
 
 fn handle_key_event(key_event: KeyEvent, app: &mut App) {
+	let keys = &app.keys;
+	let is_ctrl_c = key_event.code == KeyCode::Char('c') 
+		&& key_event.modifiers.contains(KeyModifiers::CONTROL);
+
+	if keys.quit.matches(&key_event) || is_ctrl_c {
+		app.on_quit();
+		return;
+	} else if keys.repeat.matches(&key_event) {
+		app.on_repeat();
+		return;
+	} else if keys.restart.matches(&key_event) {
+		app.on_restart();
+		return;
+	} else if keys.menu.matches(&key_event) {
+		app.on_esc();
+		return;
+	} else if keys.finish.matches(&key_event) {
+		app.on_finish();
+		return;
+	}
+
+	// unbound shortcuts shouldn't end up typed, but the likes of
+	// ctrl+backspace or alt+arrows still do what the key alone would
+	if key_event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+		&& key_event.code.as_char().is_some() {
+		return;
+	}
+
 	let mut c_opt = key_event.code.as_char();
 	if let Some(c) = c_opt.take() {
 		if c == ' ' {
@@ -81,7 +585,10 @@ fn handle_key_event(key_event: KeyEvent, app: &mut App) {
 		match key_event.code {
 			KeyCode::Backspace | KeyCode::Delete => app.on_del(),
 			KeyCode::Enter => app.on_enter(),
-			KeyCode::Esc => app.on_esc(),
+			KeyCode::Up => app.on_up(),
+			KeyCode::Down => app.on_down(),
+			KeyCode::Left => app.on_left(),
+			KeyCode::Right => app.on_right(),
 			_ => {},	
 		}
 	}
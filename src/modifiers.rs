@@ -0,0 +1,205 @@
+use rand::prelude::*;
+
+use crate::app::WordGenerator;
+
+pub const DEFAULT_PUNCTUATION_RATE: f64 = 0.25;
+pub const DEFAULT_NUMBERS_RATE: f64 = 0.15;
+
+/// Extra characters mixed into plain word lists
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Modifiers {
+	pub punctuation:		bool,
+	pub numbers:			bool,
+	/// chance of any one word getting punctuation, 0.0 to 1.0
+	pub punctuation_rate:	f64,
+	/// chance of any one word being swapped for a number, 0.0 to 1.0
+	pub numbers_rate:		f64,
+}
+
+impl Default for Modifiers {
+	fn default() -> Self {
+		Self {
+			punctuation: false,
+			numbers: false,
+			punctuation_rate: DEFAULT_PUNCTUATION_RATE,
+			numbers_rate: DEFAULT_NUMBERS_RATE,
+		}
+	}
+}
+
+impl Modifiers {
+	pub fn is_active(&self) -> bool {
+		self.punctuation || self.numbers
+	}
+}
+
+/// Wraps another generator and applies `Modifiers` to each word as it is
+/// first requested. Words are cached, so a word never changes once seen.
+pub struct ModifiedWordGenerator {
+	inner:				Box<dyn WordGenerator>,
+	modifiers:			Modifiers,
+	rng:				StdRng,
+	words:				Vec<String>,
+	capitalize_next:	bool,
+}
+
+impl ModifiedWordGenerator {
+	pub fn new(inner: Box<dyn WordGenerator>, modifiers: Modifiers, seed: u64) -> Self {
+		let mut generator = Self {
+			inner,
+			modifiers,
+			rng: StdRng::seed_from_u64(seed),
+			words: vec![],
+			capitalize_next: true,
+		};
+
+		// fixed length text is modified up front so every word can be
+		// looked at without generating it
+		if let Some(len) = generator.inner.len() && len > 0 {
+			generator.get_word_at(len - 1);
+		}
+		generator
+	}
+
+	fn modify(&mut self, mut word: String, is_last: bool) -> String {
+		if self.modifiers.numbers && self.rng.random_bool(self.modifiers.numbers_rate) {
+			let digits = self.rng.random_range(1..=4);
+			word = (0..digits)
+				.map(|_| char::from(b'0' + self.rng.random_range(0..10)))
+				.collect();
+		}
+
+		if !self.modifiers.punctuation {
+			return word;
+		}
+
+		if self.capitalize_next {
+			word = capitalize(&word);
+			self.capitalize_next = false;
+		}
+
+		if is_last {
+			word.push('.');
+			return word;
+		}
+
+		if self.rng.random_bool(self.modifiers.punctuation_rate) {
+			// weighted so commas and full stops turn up far more than the rest
+			match self.rng.random_range(0..20) {
+				0..=6 => word.push(','),
+				7..=11 => {
+					word.push('.');
+					self.capitalize_next = true;
+				},
+				12 => {
+					word.push('?');
+					self.capitalize_next = true;
+				},
+				13 => {
+					word.push('!');
+					self.capitalize_next = true;
+				},
+				14 => word.push(';'),
+				15 => word.push(':'),
+				16 => word.push_str("'s"),
+				17 | 18 => word = format!("\"{}\"", word),
+				_ => word = format!("({})", word),
+			}
+		}
+
+		word
+	}
+}
+
+fn capitalize(word: &str) -> String {
+	let mut chars = word.chars();
+	match chars.next() {
+		Some(first) => first.to_uppercase().chain(chars).collect(),
+		None => String::new(),
+	}
+}
+
+impl WordGenerator for ModifiedWordGenerator {
+	fn get_word_at(&mut self, index: usize) -> Option<String> {
+		while self.words.len() <= index {
+			let i = self.words.len();
+			let word = self.inner.get_word_at(i)?;
+			let is_last = self.inner.len() == Some(i + 1);
+			let modified = self.modify(word, is_last);
+			self.words.push(modified);
+		}
+
+		Some(self.words[index].clone())
+	}
+
+	fn get_word_at_frozen(&self, index: usize) -> Option<String> {
+		self.words.get(index).cloned()
+	}
+
+	fn len(&self) -> Option<usize> {
+		self.inner.len()
+	}
+
+	fn source(&self) -> Option<String> {
+		self.inner.source()
+	}
+
+	fn repeat(&self) -> Box<dyn WordGenerator> {
+		Box::new(Self {
+			inner: self.inner.repeat(),
+			modifiers: self.modifiers,
+			rng: self.rng.clone(),
+			words: self.words.clone(),
+			capitalize_next: self.capitalize_next,
+		})
+	}
+}
+
+#[cfg(test)]
+mod modifier_tests {
+	use super::*;
+	use crate::quotes::{Quote, QuoteGenerator, QuoteLength};
+
+	fn words(text: &str) -> Box<dyn WordGenerator> {
+		Box::new(QuoteGenerator::from(Quote {
+			text: text.to_string(),
+			source: String::new(),
+			length: QuoteLength::of(text),
+		}))
+	}
+
+	#[test]
+	fn test_punctuation() {
+		let modifiers = Modifiers { punctuation: true, punctuation_rate: 1.0, ..Default::default() };
+		let text = "the quick brown fox jumps over the lazy dog ".repeat(10);
+		let generator = ModifiedWordGenerator::new(words(&text), modifiers, 1);
+
+		let out: Vec<String> = (0..90)
+			.map(|i| generator.get_word_at_frozen(i).unwrap())
+			.collect();
+		assert!(out[0].trim_start_matches(['"', '(']).starts_with('T'));
+		assert!(out[89].ends_with('.'));
+		for pair in out.windows(2) {
+			if pair[0].ends_with(['.', '?', '!']) {
+				let next = pair[1].trim_start_matches(['"', '(']);
+				assert!(next.starts_with(char::is_uppercase), "{:?}", pair);
+			}
+		}
+	}
+
+	#[test]
+	fn test_numbers() {
+		let modifiers = Modifiers { numbers: true, numbers_rate: 1.0, ..Default::default() };
+		let mut generator = ModifiedWordGenerator::new(words("a b c"), modifiers, 1);
+
+		for i in 0..3 {
+			let word = generator.get_word_at(i).unwrap();
+			assert!(!word.is_empty() && word.chars().all(|c| c.is_ascii_digit()), "{}", word);
+		}
+		assert_eq!(generator.get_word_at(3), None);
+
+		let off = Modifiers { numbers: true, numbers_rate: 0.0, ..Default::default() };
+		let mut generator = ModifiedWordGenerator::new(words("a b c"), off, 1);
+		assert_eq!(generator.get_word_at(1), Some("b".to_string()));
+	}
+}
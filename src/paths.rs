@@ -0,0 +1,28 @@
+use std::env;
+use std::path::PathBuf;
+
+const APP_DIR: &str = "term-type";
+
+/// Where saved results live: `$XDG_DATA_HOME/term-type`, falling back to
+/// `~/.local/share/term-type`. None if neither variable is usable.
+pub fn data_dir() -> Option<PathBuf> {
+	xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Where the config file lives: `$XDG_CONFIG_HOME/term-type`, falling back
+/// to `~/.config/term-type`
+pub fn config_dir() -> Option<PathBuf> {
+	xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+fn xdg_dir(var: &str, home_fallback: &str) -> Option<PathBuf> {
+	let base = match env::var_os(var) {
+		Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+		_ => {
+			let home = env::var_os("HOME").filter(|h| !h.is_empty())?;
+			PathBuf::from(home).join(home_fallback)
+		}
+	};
+
+	Some(base.join(APP_DIR))
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::app::WordGenerator;
+
+// one quote per line: the quote, a tab, then who it's from
+static QUOTES_ENGLISH: &str=include_str!("quotes-english.txt");
+
+/// Length groups, by character count, matching MonkeyType's
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteLength {
+	/// up to 100 characters
+	Short,
+	/// 101 to 300 characters
+	Medium,
+	/// 301 to 600 characters
+	Long,
+	/// over 600 characters
+	Thicc,
+}
+
+impl QuoteLength {
+	pub fn of(text: &str) -> Self {
+		match text.chars().count() {
+			0..=100 => QuoteLength::Short,
+			101..=300 => QuoteLength::Medium,
+			301..=600 => QuoteLength::Long,
+			_ => QuoteLength::Thicc,
+		}
+	}
+}
+
+impl fmt::Display for QuoteLength {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			QuoteLength::Short => "short",
+			QuoteLength::Medium => "medium",
+			QuoteLength::Long => "long",
+			QuoteLength::Thicc => "thicc",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+impl FromStr for QuoteLength {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"short" => Ok(QuoteLength::Short),
+			"medium" => Ok(QuoteLength::Medium),
+			"long" => Ok(QuoteLength::Long),
+			"thicc" => Ok(QuoteLength::Thicc),
+			_ => Err(format!("unknown quote length '{}' (expected short, medium, long or thicc)", s)),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quote {
+	pub text:	String,
+	pub source:	String,
+	pub length:	QuoteLength,
+}
+
+/// Every bundled quote
+pub fn load_quotes() -> Vec<Quote> {
+	QUOTES_ENGLISH.lines()
+		.filter_map(|line| line.split_once('\t'))
+		.map(|(text, source)| Quote {
+			text: text.to_string(),
+			source: source.to_string(),
+			length: QuoteLength::of(text),
+		})
+		.collect()
+}
+
+/// Serves the words of a single quote, punctuation and capitals intact
+#[derive(Clone)]
+pub struct QuoteGenerator {
+	quote:	Quote,
+	words:	Vec<String>,
+}
+
+impl QuoteGenerator {
+	pub fn from(quote: Quote) -> Self {
+		let words = quote.text.split_whitespace()
+			.map(|s| s.to_string())
+			.collect();
+
+		Self { quote, words }
+	}
+
+	/// Picks a bundled quote, optionally from one length group only
+	pub fn random(length: Option<QuoteLength>, rng: &mut impl Rng) -> Self {
+		let quotes: Vec<Quote> = load_quotes().into_iter()
+			.filter(|q| length.is_none_or(|l| q.length == l))
+			.collect();
+
+		let quote = quotes.choose(rng)
+			.expect("expected a bundled quote of every length")
+			.clone();
+
+		Self::from(quote)
+	}
+}
+
+impl WordGenerator for QuoteGenerator {
+	fn get_word_at_frozen(&self, index: usize) -> Option<String> {
+		self.words.get(index).cloned()
+	}
+
+	fn get_word_at(&mut self, index: usize) -> Option<String> {
+		self.get_word_at_frozen(index)
+	}
+
+	fn len(&self) -> Option<usize> {
+		Some(self.words.len())
+	}
+
+	fn source(&self) -> Option<String> {
+		Some(self.quote.source.clone())
+	}
+
+	fn repeat(&self) -> Box<dyn WordGenerator> {
+		Box::new(self.clone())
+	}
+}
+
+#[cfg(test)]
+mod quote_tests {
+	use super::*;
+
+	#[test]
+	fn test_bundled_quotes() {
+		let quotes = load_quotes();
+		assert_eq!(quotes.len(), QUOTES_ENGLISH.lines().count());
+
+		for length in [QuoteLength::Short, QuoteLength::Medium,
+				QuoteLength::Long, QuoteLength::Thicc] {
+			assert!(quotes.iter().any(|q| q.length == length),
+				"no {} quotes", length);
+			assert_eq!(QuoteGenerator::random(Some(length), &mut rand::rng()).quote.length, length);
+		}
+	}
+
+	#[test]
+	fn test_quote_generator() {
+		let generator = QuoteGenerator::from(Quote {
+			text: "Call me Ishmael.".to_string(),
+			source: "Herman Melville, Moby-Dick".to_string(),
+			length: QuoteLength::Short,
+		});
+
+		assert_eq!(generator.len(), Some(3));
+		assert_eq!(generator.get_word_at_frozen(0), Some("Call".to_string()));
+		assert_eq!(generator.get_word_at_frozen(2), Some("Ishmael.".to_string()));
+		assert_eq!(generator.get_word_at_frozen(3), None);
+		assert_eq!(generator.source(), Some("Herman Melville, Moby-Dick".to_string()));
+	}
+}
@@ -0,0 +1,504 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::{Session, SessionStats, TestMode};
+use crate::share::TestCode;
+
+pub const DEFAULT_PORT: u16 = 7878;
+/// How long everyone waits between the host starting a race and typing
+pub const COUNTDOWN: Duration = Duration::from_secs(3);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Sends happen between frames, so a racer who stops reading is given up on
+/// rather than left to freeze the screen
+const WRITE_TIMEOUT: Duration = Duration::from_millis(250);
+/// Longer lines than this end the connection, rather than being read in
+/// for as long as they go on
+const MAX_LINE: u64 = 64 * 1024;
+/// Names are cut down to this many characters, to keep the standings short
+const MAX_NAME: usize = 32;
+
+/// What racers send each other, one JSON object per line
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+	/// from a racer, first thing after connecting
+	Join { name: String },
+	/// from the host: the test everyone types, and how long until typing
+	/// starts
+	Start { code: String, countdown_ms: u64 },
+	/// from a racer, how far they've got
+	Progress(Progress),
+	/// from the host, how far everyone's got. `you` is the receiver's
+	/// place in `racers`.
+	Standings { racers: Vec<Racer>, you: usize },
+}
+
+/// How far a racer has got through the test
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Progress {
+	/// from 0 to 1
+	pub done:		f32,
+	pub wpm:		f32,
+	/// None until the racer has finished
+	pub acc:		Option<f32>,
+	pub finished:	bool,
+}
+
+impl Progress {
+	/// Where `session` has got to, by the clock for timed tests and by
+	/// words for the rest
+	pub fn from(session: &Session) -> Self {
+		let live = session.live_stats();
+		let done = match (&session.mode, session.get_time_left_s(), live.words_total) {
+			(TestMode::Time(s), Some(left), _) if *s > 0 => 1.0 - left as f32 / *s as f32,
+			(_, _, Some(total)) if total > 0 => live.words_done as f32 / total as f32,
+			_ => 0.0,
+		};
+		// nobody's typed anything yet before the first keystroke
+		let wpm = if session.get_age_s().is_some() { live.wpm } else { 0.0 };
+		Self { done, wpm, acc: None, finished: false }
+	}
+
+	/// A finished racer's result
+	pub fn finished(stats: &SessionStats) -> Self {
+		Self { done: 1.0, wpm: stats.wpm, acc: stats.acc, finished: true }
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Racer {
+	pub name:		String,
+	pub progress:	Progress,
+	/// 1 for the first to finish, None until they have
+	pub place:		Option<usize>,
+	/// whether they've dropped out of the race
+	pub left:		bool,
+}
+
+/// A race the host has started
+#[derive(Clone, Debug, PartialEq)]
+pub struct Start {
+	pub code:		TestCode,
+	pub countdown:	Duration,
+}
+
+// what a connection's reader thread hands over: a message, or None once the
+// connection has closed
+type Incoming = (usize, Option<Message>);
+
+// reads messages from `stream` on a thread of its own until it closes
+fn listen(stream: TcpStream, id: usize, incoming: Sender<Incoming>) {
+	thread::spawn(move || {
+		let mut reader = BufReader::new(stream);
+		let mut line = vec![];
+		loop {
+			line.clear();
+			// stops at the limit, so a line that's cut off there is too long
+			match (&mut reader).take(MAX_LINE).read_until(b'\n', &mut line) {
+				Ok(_) if line.ends_with(b"\n") => {},
+				_ => break,
+			}
+			// anything unreadable is skipped rather than ending the race
+			if let Ok(message) = serde_json::from_slice(&line)
+				&& incoming.send((id, Some(message))).is_err() {
+				return;
+			}
+		}
+		let _ = incoming.send((id, None));
+	});
+}
+
+// for writing to `stream` without waiting on it for long
+fn connected(stream: TcpStream) -> io::Result<TcpStream> {
+	stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+	Ok(stream)
+}
+
+fn send(stream: &mut TcpStream, message: &Message) -> io::Result<()> {
+	let mut line = serde_json::to_string(message)?;
+	line.push('\n');
+	stream.write_all(line.as_bytes())
+}
+
+/// Marks `racer` as finished, in the next place along
+fn finish(racers: &mut [Racer], racer: usize) {
+	if racers[racer].place.is_none() {
+		let finished = racers.iter().filter(|r| r.place.is_some()).count();
+		racers[racer].place = Some(finished + 1);
+	}
+}
+
+struct Peer {
+	/// None once they've disconnected
+	stream:	Option<TcpStream>,
+	/// index into the racers, once they've joined
+	racer:	Option<usize>,
+}
+
+/// Runs a race for everyone who joins over the network, and takes part in
+/// it too
+pub struct Host {
+	listener:		TcpListener,
+	peers:			Vec<Peer>,
+	incoming_tx:	Sender<Incoming>,
+	incoming:		Receiver<Incoming>,
+	/// the host first, then everyone else in the order they joined
+	pub racers:		Vec<Racer>,
+}
+
+impl Host {
+	pub fn bind(addr: impl ToSocketAddrs, name: &str) -> io::Result<Self> {
+		let listener = TcpListener::bind(addr)?;
+		listener.set_nonblocking(true)?;
+		let (incoming_tx, incoming) = mpsc::channel();
+
+		Ok(Self {
+			listener,
+			peers: vec![],
+			incoming_tx,
+			incoming,
+			racers: vec![Racer { name: name.to_string(), ..Racer::default() }],
+		})
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.listener.local_addr()
+	}
+
+	/// Lets in anyone waiting to connect and deals with what they've sent
+	pub fn poll(&mut self) {
+		while let Ok((stream, _)) = self.listener.accept() {
+			// the listener's non-blocking mode is inherited on some platforms
+			let streams = stream.set_nonblocking(false)
+				.and_then(|_| connected(stream))
+				.and_then(|stream| Ok((stream.try_clone()?, stream)));
+			if let Ok((reader, stream)) = streams {
+				listen(reader, self.peers.len(), self.incoming_tx.clone());
+				self.peers.push(Peer { stream: Some(stream), racer: None });
+			}
+		}
+
+		let mut changed = false;
+		while let Ok((id, message)) = self.incoming.try_recv() {
+			let peer = &mut self.peers[id];
+			match (message, peer.racer) {
+				(Some(Message::Join { name }), None) => {
+					peer.racer = Some(self.racers.len());
+					let name = name.chars().take(MAX_NAME).collect();
+					self.racers.push(Racer { name, ..Racer::default() });
+				},
+				(Some(Message::Progress(progress)), Some(racer)) => {
+					self.racers[racer].progress = progress;
+					if progress.finished {
+						finish(&mut self.racers, racer);
+					}
+				},
+				(None, racer) => {
+					peer.stream = None;
+					if let Some(racer) = racer {
+						self.racers[racer].left = true;
+					}
+				},
+				_ => continue,
+			}
+			changed = true;
+		}
+		if changed {
+			self.broadcast_standings();
+		}
+	}
+
+	/// Starts everyone on the test in `code` together
+	pub fn start(&mut self, code: &TestCode) {
+		for racer in &mut self.racers {
+			racer.progress = Progress::default();
+			racer.place = None;
+		}
+		let start = Message::Start {
+			code: code.to_string(),
+			countdown_ms: COUNTDOWN.as_millis() as u64,
+		};
+		self.broadcast(|_| start.clone());
+		self.broadcast_standings();
+	}
+
+	/// Updates the host's own progress
+	pub fn report(&mut self, progress: Progress) {
+		self.racers[0].progress = progress;
+		if progress.finished {
+			finish(&mut self.racers, 0);
+		}
+		self.broadcast_standings();
+	}
+
+	fn broadcast_standings(&mut self) {
+		let racers = self.racers.clone();
+		self.broadcast(|you| Message::Standings { racers: racers.clone(), you });
+	}
+
+	// sends everyone who's joined a message, given their place in the racers.
+	// Anyone who can't be reached, or can't keep up, has left. A send that
+	// timed out may have written half a line, so they're hung up on.
+	fn broadcast(&mut self, message: impl Fn(usize) -> Message) {
+		for peer in &mut self.peers {
+			let (Some(stream), Some(racer)) = (&mut peer.stream, peer.racer) else {
+				continue;
+			};
+			if send(stream, &message(racer)).is_err() {
+				let _ = stream.shutdown(Shutdown::Both);
+				peer.stream = None;
+				self.racers[racer].left = true;
+			}
+		}
+	}
+}
+
+// the reader threads hold clones of the streams, so dropping ours isn't
+// enough to hang up
+impl Drop for Host {
+	fn drop(&mut self) {
+		for stream in self.peers.iter().filter_map(|peer| peer.stream.as_ref()) {
+			let _ = stream.shutdown(Shutdown::Both);
+		}
+	}
+}
+
+/// Takes part in a race someone else is hosting
+pub struct Client {
+	stream:			TcpStream,
+	incoming:		Receiver<Incoming>,
+	/// where everyone is, as last heard from the host
+	pub racers:		Vec<Racer>,
+	/// this racer's place in `racers`
+	pub you:		usize,
+	/// false once the host has gone
+	pub connected:	bool,
+}
+
+impl Client {
+	/// Connects to a host at `addr`, on the default port if it doesn't say
+	pub fn connect(addr: &str, name: &str) -> io::Result<Self> {
+		let addr = if addr.contains(':') { addr.to_string() } else { format!("{}:{}", addr, DEFAULT_PORT) };
+		let socket_addr = addr.to_socket_addrs()?
+			.next()
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("couldn't find '{}'", addr)))?;
+
+		let mut stream = connected(TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT)?)?;
+		let (incoming_tx, incoming) = mpsc::channel();
+		listen(stream.try_clone()?, 0, incoming_tx);
+		send(&mut stream, &Message::Join { name: name.to_string() })?;
+
+		Ok(Self {
+			stream,
+			incoming,
+			racers: vec![Racer { name: name.to_string(), ..Racer::default() }],
+			you: 0,
+			connected: true,
+		})
+	}
+
+	/// Deals with what the host has sent, returning the race it's started
+	/// if it has
+	pub fn poll(&mut self) -> Option<Start> {
+		let mut start = None;
+		while let Ok((_, message)) = self.incoming.try_recv() {
+			match message {
+				Some(Message::Start { code, countdown_ms }) => if let Ok(code) = code.parse() {
+					start = Some(Start { code, countdown: Duration::from_millis(countdown_ms) });
+				},
+				Some(Message::Standings { racers, you }) => {
+					self.racers = racers;
+					self.you = you;
+				},
+				Some(_) => {},
+				None => self.connected = false,
+			}
+		}
+		start
+	}
+
+	pub fn report(&mut self, progress: Progress) {
+		if let Some(racer) = self.racers.get_mut(self.you) {
+			racer.progress = progress;
+		}
+		if self.connected && send(&mut self.stream, &Message::Progress(progress)).is_err() {
+			let _ = self.stream.shutdown(Shutdown::Both);
+			self.connected = false;
+		}
+	}
+}
+
+impl Drop for Client {
+	fn drop(&mut self) {
+		let _ = self.stream.shutdown(Shutdown::Both);
+	}
+}
+
+/// Either end of a race
+pub enum Race {
+	Host(Host),
+	Client(Client),
+}
+
+impl Race {
+	pub fn racers(&self) -> &[Racer] {
+		match self {
+			Race::Host(host) => &host.racers,
+			Race::Client(client) => &client.racers,
+		}
+	}
+
+	/// This racer's place in `racers()`
+	pub fn you(&self) -> usize {
+		match self {
+			Race::Host(_) => 0,
+			Race::Client(client) => client.you,
+		}
+	}
+
+	pub fn is_host(&self) -> bool {
+		matches!(self, Race::Host(_))
+	}
+
+	/// Catches up with the network, returning a race the host has started
+	pub fn poll(&mut self) -> Option<Start> {
+		match self {
+			Race::Host(host) => {
+				host.poll();
+				None
+			},
+			Race::Client(client) => client.poll(),
+		}
+	}
+
+	pub fn report(&mut self, progress: Progress) {
+		match self {
+			Race::Host(host) => host.report(progress),
+			Race::Client(client) => client.report(progress),
+		}
+	}
+
+	/// e.g. "hosting on port 7878", for the lobby
+	pub fn status(&self) -> String {
+		match self {
+			Race::Host(host) => match host.local_addr() {
+				Ok(addr) => format!("hosting on port {}", addr.port()),
+				Err(_) => "hosting".to_string(),
+			},
+			Race::Client(client) if !client.connected => "the host has left the race".to_string(),
+			Race::Client(client) => match client.stream.peer_addr() {
+				Ok(addr) => format!("joined {}", addr),
+				Err(_) => "joined".to_string(),
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod race_tests {
+	use super::*;
+	use std::time::Instant;
+
+	/// Polls until `done`, failing the test if it takes more than a few
+	/// seconds
+	pub(crate) fn wait_until(mut done: impl FnMut() -> bool) {
+		let start = Instant::now();
+		while !done() {
+			assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+			thread::sleep(Duration::from_millis(5));
+		}
+	}
+
+	#[test]
+	fn test_loopback_race() {
+		let mut host = Host::bind("127.0.0.1:0", "host").unwrap();
+		let addr = host.local_addr().unwrap().to_string();
+		let mut guest = Client::connect(&addr, "guest").unwrap();
+
+		wait_until(|| {
+			host.poll();
+			host.racers.len() == 2
+		});
+		assert_eq!(host.racers[1].name, "guest");
+
+		let code: TestCode = "w25/english-1k/-/2a".parse().unwrap();
+		host.start(&code);
+		let mut start = None;
+		wait_until(|| {
+			start = start.take().or_else(|| guest.poll());
+			start.is_some() && guest.racers.len() == 2
+		});
+		assert_eq!(start.unwrap(), Start { code, countdown: COUNTDOWN });
+		assert_eq!(guest.you, 1);
+
+		guest.report(Progress { done: 0.5, wpm: 80.0, ..Progress::default() });
+		wait_until(|| {
+			host.poll();
+			host.racers[1].progress.done == 0.5
+		});
+
+		// first past the post wins, whatever their speed
+		guest.report(Progress { done: 1.0, wpm: 80.0, acc: Some(0.9), finished: true });
+		wait_until(|| {
+			host.poll();
+			host.racers[1].place.is_some()
+		});
+		host.report(Progress { done: 1.0, wpm: 120.0, acc: Some(1.0), finished: true });
+		wait_until(|| {
+			guest.poll();
+			guest.racers[0].place.is_some()
+		});
+		assert_eq!(guest.racers[1].place, Some(1));
+		assert_eq!(guest.racers[0].place, Some(2));
+
+		drop(guest);
+		wait_until(|| {
+			host.poll();
+			host.racers[1].left
+		});
+	}
+
+	#[test]
+	fn test_stalled_racer() {
+		let mut host = Host::bind("127.0.0.1:0", "host").unwrap();
+		let mut guest = TcpStream::connect(host.local_addr().unwrap()).unwrap();
+		send(&mut guest, &Message::Join { name: "guest".to_string() }).unwrap();
+		wait_until(|| {
+			host.poll();
+			host.racers.len() == 2
+		});
+
+		// the guest never reads, so sooner or later sending blocks
+		let start = Instant::now();
+		while !host.racers[1].left {
+			assert!(start.elapsed() < Duration::from_secs(20), "never gave up on the guest");
+			host.report(Progress::default());
+		}
+	}
+
+	#[test]
+	fn test_line_limit() {
+		let mut host = Host::bind("127.0.0.1:0", "host").unwrap();
+		let mut guest = TcpStream::connect(host.local_addr().unwrap()).unwrap();
+		let name = "x".repeat(100);
+		send(&mut guest, &Message::Join { name }).unwrap();
+		wait_until(|| {
+			host.poll();
+			host.racers.len() == 2
+		});
+		assert_eq!(host.racers[1].name.len(), MAX_NAME);
+
+		// a line with no end in sight is hung up on
+		let long = vec![b'x'; MAX_LINE as usize + 1];
+		let _ = guest.write_all(&long);
+		wait_until(|| {
+			host.poll();
+			host.racers[1].left
+		});
+	}
+}
@@ -0,0 +1,203 @@
+use std::time::{Duration, Instant};
+
+use crate::app::{Keystroke, Session};
+use crate::history::HistoryEntry;
+
+/// How fast a replay can run, relative to the test itself
+pub const REPLAY_SPEEDS: [f64; 3] = [0.5, 1.0, 2.0];
+
+/// A saved test played back keystroke by keystroke into a session of its
+/// own, so the typing screen can draw it exactly as it happened
+pub struct Replay {
+	/// what's been played so far
+	pub session:	Session,
+	/// a copy of the session before anything was played, to start over from
+	start:			Session,
+	keystrokes:		Vec<Keystroke>,
+	/// how many keystrokes have been played
+	pub played:		usize,
+	/// how far into the test the replay is, in milliseconds
+	clock_ms:		f64,
+	/// one of `REPLAY_SPEEDS`
+	pub speed:		f64,
+	pub paused:		bool,
+	last_tick:		Option<Instant>,
+}
+
+impl Replay {
+	pub fn from(entry: &HistoryEntry) -> Self {
+		let start = Session::replay(entry);
+		Self {
+			session: start.repeat(),
+			start,
+			keystrokes: entry.keystrokes.clone(),
+			played: 0,
+			clock_ms: 0.0,
+			speed: 1.0,
+			paused: false,
+			last_tick: None,
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.keystrokes.len()
+	}
+
+	pub fn is_done(&self) -> bool {
+		self.played == self.keystrokes.len()
+	}
+
+	/// Seconds into the test the replay has reached
+	pub fn clock_s(&self) -> f64 {
+		self.clock_ms / 1000.0
+	}
+
+	/// Called on every refresh, to keep up with the clock
+	pub fn on_tick(&mut self) {
+		let now = Instant::now();
+		if let Some(last_tick) = self.last_tick.replace(now) {
+			self.advance(now - last_tick);
+		}
+	}
+
+	/// Moves the clock on by `elapsed` (at the replay's speed) and plays
+	/// every keystroke it passes
+	pub fn advance(&mut self, elapsed: Duration) {
+		if self.paused || self.is_done() {
+			return;
+		}
+
+		self.play_until(self.clock_ms + elapsed.as_secs_f64() * 1000.0 * self.speed);
+		if self.is_done() {
+			self.paused = true;
+		}
+	}
+
+	/// Plays every keystroke up to `clock_ms` into the test
+	pub fn play_until(&mut self, clock_ms: f64) {
+		self.clock_ms = clock_ms;
+		while let Some(keystroke) = self.keystrokes.get(self.played)
+			&& keystroke.time_ms as f64 <= self.clock_ms {
+			self.session.play(keystroke.action);
+			self.played += 1;
+		}
+	}
+
+	/// Pauses or resumes. Once it's over, it starts again from the top.
+	pub fn toggle_pause(&mut self) {
+		if self.is_done() {
+			self.seek(0);
+			self.paused = false;
+		} else {
+			self.paused = !self.paused;
+		}
+		self.last_tick = None;
+	}
+
+	/// Pauses and plays one keystroke more, or one fewer
+	pub fn step(&mut self, forward: bool) {
+		self.paused = true;
+		let played = if forward {
+			(self.played + 1).min(self.keystrokes.len())
+		} else {
+			self.played.saturating_sub(1)
+		};
+		self.seek(played);
+	}
+
+	pub fn change_speed(&mut self, faster: bool) {
+		let i = REPLAY_SPEEDS.iter().position(|speed| *speed == self.speed).unwrap_or(1);
+		let i = if faster {
+			(i + 1).min(REPLAY_SPEEDS.len() - 1)
+		} else {
+			i.saturating_sub(1)
+		};
+		self.speed = REPLAY_SPEEDS[i];
+	}
+
+	// deletes can't be undone one by one, so going back plays everything
+	// up to that point again
+	fn seek(&mut self, played: usize) {
+		if played < self.played {
+			self.session = self.start.repeat();
+			self.played = 0;
+		}
+		while self.played < played {
+			self.session.play(self.keystrokes[self.played].action);
+			self.played += 1;
+		}
+		self.clock_ms = match played {
+			0 => 0.0,
+			n => self.keystrokes[n - 1].time_ms as f64,
+		};
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod replay_tests {
+	use super::*;
+	use crate::app::SessionStats;
+
+	/// A test on `text` typed as `keys`, where '<' deletes, with a tenth of
+	/// a second between keystrokes
+	pub(crate) fn typed_entry(text: &str, keys: &str) -> HistoryEntry {
+		let mut session = Session::from(text.to_string());
+		for c in keys.chars() {
+			match c {
+				' ' => session.on_space(),
+				'<' => session.on_del(),
+				c => session.on_char(c),
+			}
+		}
+		// spread the keystrokes out a tenth of a second apart
+		for (i, keystroke) in session.keystrokes.iter_mut().enumerate() {
+			keystroke.time_ms = i as u64 * 100;
+		}
+		HistoryEntry::new(&session, &SessionStats::default())
+	}
+
+	#[test]
+	fn test_replay() {
+		let entry = typed_entry("the quick fox", "teh<<he quikc<<ck f");
+		assert!(entry.can_replay());
+		let mut replay = Replay::from(&entry);
+		assert_eq!(replay.len(), 19);
+
+		replay.advance(Duration::from_millis(0));
+		assert_eq!(replay.session.input, vec!["t"]);
+		replay.advance(Duration::from_millis(550));
+		assert_eq!(replay.played, 6);
+		assert_eq!(replay.session.input, vec!["th"]);
+
+		replay.speed = 2.0;
+		replay.advance(Duration::from_millis(550));
+		assert_eq!(replay.played, 17);
+		assert_eq!(replay.session.input, vec!["the", "quick"]);
+
+		replay.toggle_pause();
+		replay.advance(Duration::from_secs(10));
+		assert_eq!(replay.played, 17);
+
+		replay.step(false);
+		assert_eq!(replay.session.input, vec!["the", "quic"]);
+		assert!(replay.paused);
+		replay.step(true);
+		assert_eq!(replay.session.input, vec!["the", "quick"]);
+
+		replay.toggle_pause();
+		replay.advance(Duration::from_secs(10));
+		assert!(replay.is_done() && replay.paused);
+		assert_eq!(replay.session.input, vec!["the", "quick", "f"]);
+		assert_eq!(replay.session.keystrokes.iter().map(|k| k.action).collect::<Vec<_>>(),
+			entry.keystrokes.iter().map(|k| k.action).collect::<Vec<_>>());
+
+		// from the top again
+		replay.toggle_pause();
+		assert_eq!(replay.played, 0);
+		assert!(replay.session.keystrokes.is_empty());
+
+		replay.change_speed(false);
+		replay.change_speed(false);
+		assert_eq!(replay.speed, 0.5);
+	}
+}
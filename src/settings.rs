@@ -0,0 +1,349 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::code::DEFAULT_CODE_LANGUAGE;
+use crate::config::{Config, ModeName};
+use crate::ghost::Pace;
+use crate::paths;
+use crate::quotes::QuoteLength;
+use crate::theme::DEFAULT_THEME;
+use crate::wordlist::DEFAULT_LANGUAGE;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+pub const DEFAULT_WORD_COUNT: usize = 25;
+pub const DEFAULT_TIME_S: u64 = 30;
+/// zen tests only end when asked to by default
+pub const DEFAULT_ZEN_IDLE_S: u64 = 0;
+
+/// What the menu offers for each kind of test
+pub const WORD_COUNTS: [usize; 4] = [10, 25, 50, 100];
+pub const TIME_LIMITS: [u64; 4] = [15, 30, 60, 120];
+pub const ZEN_IDLE_LIMITS: [u64; 4] = [0, 5, 10, 30];
+pub const QUOTE_LENGTHS: [QuoteLength; 4] =
+	[QuoteLength::Short, QuoteLength::Medium, QuoteLength::Long, QuoteLength::Thicc];
+
+/// The test the menu is set up to start. Changes made in the menu are
+/// saved, so the next run picks up where this one left off.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+	/// where changes are saved, nowhere if None
+	#[serde(skip)]
+	path:				Option<PathBuf>,
+	pub mode:			ModeName,
+	pub words:			usize,
+	/// in seconds
+	pub time:			u64,
+	pub quote:			QuoteLength,
+	/// seconds without typing before a zen test ends, 0 for never
+	pub zen_idle:		u64,
+	/// what custom tests type, from `--text`
+	pub text:			Option<String>,
+	pub wordlist:		Option<PathBuf>,
+	pub language:		Option<String>,
+	pub punctuation:	bool,
+	pub numbers:		bool,
+	/// language of the bundled snippets code tests use, unless there's a
+	/// `source` file
+	pub code_language:	String,
+	/// what code tests type, from `--source`
+	pub source:			Option<PathBuf>,
+	pub skip_indent:	bool,
+	/// how fast the ghost caret goes
+	pub pace:			Pace,
+	pub theme:			String,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Self {
+			path: None,
+			mode: ModeName::Words,
+			words: DEFAULT_WORD_COUNT,
+			time: DEFAULT_TIME_S,
+			quote: QuoteLength::Medium,
+			zen_idle: DEFAULT_ZEN_IDLE_S,
+			text: None,
+			wordlist: None,
+			language: None,
+			punctuation: false,
+			numbers: false,
+			code_language: DEFAULT_CODE_LANGUAGE.to_string(),
+			source: None,
+			skip_indent: false,
+			pace: Pace::Off,
+			theme: DEFAULT_THEME.to_string(),
+		}
+	}
+}
+
+impl Settings {
+	/// What the menu starts with before anything has been saved
+	pub fn from_config(config: &Config) -> Self {
+		let defaults = Self::default();
+		Self {
+			path: None,
+			mode: config.mode.unwrap_or(defaults.mode),
+			words: config.words.unwrap_or(defaults.words),
+			time: config.time.unwrap_or(defaults.time),
+			quote: config.quote.unwrap_or(defaults.quote),
+			zen_idle: config.zen_idle.unwrap_or(defaults.zen_idle),
+			text: None,
+			wordlist: config.wordlist.clone(),
+			language: config.language.clone(),
+			punctuation: config.punctuation,
+			numbers: config.numbers,
+			code_language: config.code_language.clone().unwrap_or(defaults.code_language),
+			source: None,
+			skip_indent: config.skip_indent,
+			pace: config.pace.unwrap_or(defaults.pace),
+			theme: config.theme.clone().unwrap_or(defaults.theme),
+		}
+	}
+
+	/// Loads the saved settings from the user's data directory, or
+	/// `defaults` if nothing has been saved yet
+	pub fn load(defaults: Self) -> io::Result<Self> {
+		match paths::data_dir() {
+			Some(dir) => Self::load_from(dir.join(SETTINGS_FILE), defaults),
+			None => Ok(defaults),
+		}
+	}
+
+	pub fn load_from(path: PathBuf, defaults: Self) -> io::Result<Self> {
+		let contents = match fs::read_to_string(&path) {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == io::ErrorKind::NotFound =>
+				return Ok(Self { path: Some(path), ..defaults }),
+			Err(e) => return Err(e),
+		};
+
+		// the file is only ever written by term-type, so if it's damaged
+		// starting over beats refusing to start
+		let settings = serde_json::from_str(&contents).unwrap_or(defaults);
+		Ok(Self { path: Some(path), ..settings })
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		let Some(path) = &self.path else {
+			return Ok(());
+		};
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(path, serde_json::to_string_pretty(self)?)
+	}
+
+	/// Name of the list random words come from, as shown in the menu
+	pub fn word_list_name(&self) -> String {
+		match (&self.wordlist, &self.language) {
+			(Some(path), _) => file_name(path),
+			(None, Some(language)) => language.clone(),
+			(None, None) => DEFAULT_LANGUAGE.to_string(),
+		}
+	}
+
+	/// What code tests type from, as shown in the menu
+	pub fn code_name(&self) -> String {
+		match &self.source {
+			Some(path) => file_name(path),
+			None => self.code_language.clone(),
+		}
+	}
+
+	/// Takes on whatever `item` changes in the menu from `other`, leaving
+	/// the rest alone
+	pub fn copy_item(&mut self, other: &Self, item: MenuItem) {
+		match item {
+			MenuItem::Mode => {
+				self.mode = other.mode;
+				self.text = other.text.clone();
+			},
+			MenuItem::Length => match other.mode {
+				ModeName::Words | ModeName::Adaptive => self.words = other.words,
+				ModeName::Time => self.time = other.time,
+				ModeName::Quote => self.quote = other.quote,
+				ModeName::Zen => self.zen_idle = other.zen_idle,
+				ModeName::Code => {
+					self.source = other.source.clone();
+					self.code_language = other.code_language.clone();
+				},
+				ModeName::Custom => {},
+			},
+			MenuItem::WordList => {
+				self.wordlist = other.wordlist.clone();
+				self.language = other.language.clone();
+			},
+			MenuItem::Punctuation => self.punctuation = other.punctuation,
+			MenuItem::Numbers => self.numbers = other.numbers,
+			MenuItem::Indentation => self.skip_indent = other.skip_indent,
+			MenuItem::Pace => self.pace = other.pace,
+			MenuItem::Theme => self.theme = other.theme.clone(),
+		}
+	}
+
+	/// Modes the menu can switch between. Custom tests need some text.
+	pub fn modes(&self) -> Vec<ModeName> {
+		let mut modes = vec![ModeName::Words, ModeName::Time, ModeName::Quote, ModeName::Adaptive];
+		if self.text.is_some() {
+			modes.push(ModeName::Custom);
+		}
+		modes.push(ModeName::Code);
+		modes.push(ModeName::Zen);
+		modes
+	}
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name()
+		.map_or(path.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// The rows of the menu, top to bottom
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuItem {
+	Mode,
+	Length,
+	WordList,
+	Punctuation,
+	Numbers,
+	Indentation,
+	Pace,
+	Theme,
+}
+
+impl MenuItem {
+	pub const ALL: [MenuItem; 8] = [
+		MenuItem::Mode,
+		MenuItem::Length,
+		MenuItem::WordList,
+		MenuItem::Punctuation,
+		MenuItem::Numbers,
+		MenuItem::Indentation,
+		MenuItem::Pace,
+		MenuItem::Theme,
+	];
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			MenuItem::Mode => "mode",
+			MenuItem::Length => "length",
+			MenuItem::WordList => "word list",
+			MenuItem::Punctuation => "punctuation",
+			MenuItem::Numbers => "numbers",
+			MenuItem::Indentation => "indentation",
+			MenuItem::Pace => "ghost",
+			MenuItem::Theme => "theme",
+		}
+	}
+
+	/// Whether the row does anything in `mode`
+	pub fn applies_to(&self, mode: ModeName) -> bool {
+		match self {
+			MenuItem::Length => mode != ModeName::Custom,
+			MenuItem::WordList | MenuItem::Punctuation | MenuItem::Numbers =>
+				matches!(mode, ModeName::Words | ModeName::Time | ModeName::Adaptive),
+			MenuItem::Indentation => mode == ModeName::Code,
+			MenuItem::Pace => mode != ModeName::Zen,
+			MenuItem::Mode | MenuItem::Theme => true,
+		}
+	}
+}
+
+/// The option after (or before) `current`, wrapping around at the ends.
+/// Starts from the first option if `current` isn't one of them.
+pub fn cycle<T: PartialEq + Clone>(options: &[T], current: &T, forward: bool) -> T {
+	let len = options.len();
+	let next = match options.iter().position(|option| option == current) {
+		Some(i) if forward => (i + 1) % len,
+		Some(i) => (i + len - 1) % len,
+		None => 0,
+	};
+	options[next].clone()
+}
+
+/// Like `cycle`, but a value that isn't one of the presets (like a word
+/// count from the command line) moves to the nearest preset in that direction
+pub fn cycle_number<T: PartialOrd + Copy>(presets: &[T], current: T, forward: bool) -> T {
+	let next = if forward {
+		presets.iter().find(|&&preset| preset > current)
+	} else {
+		presets.iter().rev().find(|&&preset| preset < current)
+	};
+
+	match next {
+		Some(&preset) => preset,
+		None if forward => presets[0],
+		None => presets[presets.len() - 1],
+	}
+}
+
+#[cfg(test)]
+mod settings_tests {
+	use super::*;
+
+	#[test]
+	fn test_cycle() {
+		assert_eq!(cycle_number(&WORD_COUNTS, 25, true), 50);
+		assert_eq!(cycle_number(&WORD_COUNTS, 100, true), 10);
+		assert_eq!(cycle_number(&WORD_COUNTS, 10, false), 100);
+		assert_eq!(cycle_number(&WORD_COUNTS, 37, true), 50);
+		assert_eq!(cycle_number(&WORD_COUNTS, 37, false), 25);
+
+		assert_eq!(cycle(&QUOTE_LENGTHS, &QuoteLength::Thicc, true), QuoteLength::Short);
+		assert_eq!(cycle(&QUOTE_LENGTHS, &QuoteLength::Short, false), QuoteLength::Thicc);
+		assert_eq!(cycle(&["a", "b"], &"c", false), "a");
+	}
+
+	#[test]
+	fn test_save_and_load() {
+		let dir = std::env::temp_dir()
+			.join(format!("term-type-settings-test-{}", std::process::id()));
+		let path = dir.join(SETTINGS_FILE);
+		let _ = fs::remove_dir_all(&dir);
+
+		let defaults = Settings { time: 60, ..Settings::default() };
+		let mut settings = Settings::load_from(path.clone(), defaults.clone()).unwrap();
+		assert_eq!(settings.time, 60);
+
+		settings.mode = ModeName::Time;
+		settings.numbers = true;
+		settings.language = Some("german".to_string());
+		settings.save().unwrap();
+
+		let loaded = Settings::load_from(path.clone(), defaults).unwrap();
+		assert_eq!(loaded, settings);
+		assert_eq!(loaded.word_list_name(), "german");
+
+		fs::write(&path, "not json").unwrap();
+		let damaged = Settings::load_from(path, Settings::default()).unwrap();
+		assert_eq!(damaged.mode, ModeName::Words);
+
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn test_copy_item() {
+		let mut saved = Settings::default();
+		let current = Settings {
+			mode: ModeName::Time,
+			time: 120,
+			words: 50,
+			numbers: true,
+			..Settings::default()
+		};
+
+		saved.copy_item(&current, MenuItem::Length);
+		assert_eq!(saved.time, 120);
+		assert_eq!(saved.words, DEFAULT_WORD_COUNT);
+		assert_eq!(saved.mode, ModeName::Words);
+		assert!(!saved.numbers);
+
+		saved.copy_item(&current, MenuItem::Numbers);
+		assert!(saved.numbers);
+	}
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::app::{Session, TestMode};
+use crate::modifiers::Modifiers;
+use crate::quotes::QuoteLength;
+use crate::wordlist::{Sampling, Weighting, WordList};
+
+/// Everything that decides which words a test has, short enough to paste
+/// to someone so they can type the same ones. Written like
+/// `t30/english-1k/p25n15zr10/1f2e3d4c`: the mode, the word list, what
+/// the words are drawn with (`-` for the defaults) and the seed in hex.
+/// Quotes only need the length and seed, e.g. `qm/1f2e3d4c`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestCode {
+	/// words, time or quote, the only tests anyone else can reproduce
+	pub mode:		TestMode,
+	/// None for quotes
+	pub word_list:	Option<String>,
+	pub modifiers:	Modifiers,
+	pub sampling:	Sampling,
+	pub seed:		u64,
+}
+
+impl TestCode {
+	/// None for tests that aren't down to the seed alone: custom text, zen
+	/// and adaptive tests, which depend on who's typing
+	pub fn from(session: &Session) -> Option<Self> {
+		let seed = session.seed?;
+		match session.mode {
+			TestMode::Words(_) | TestMode::Time(_) => Some(Self {
+				mode: session.mode.clone(),
+				word_list: Some(session.word_list.clone()?),
+				modifiers: session.modifiers,
+				sampling: session.sampling,
+				seed,
+			}),
+			TestMode::Quote(_) => Some(Self {
+				mode: session.mode.clone(),
+				word_list: None,
+				modifiers: Modifiers::default(),
+				sampling: Sampling::default(),
+				seed,
+			}),
+			_ => None,
+		}
+	}
+
+	/// A fresh test on the code's words. Fails if its word list isn't
+	/// installed here.
+	pub fn session(&self) -> io::Result<Session> {
+		let word_list = || WordList::installed(self.word_list.as_deref().unwrap_or_default());
+		Ok(match &self.mode {
+			TestMode::Words(n) => Session::random_with_size(*n, &word_list()?, self.sampling, self.seed)
+				.with_modifiers(self.modifiers),
+			TestMode::Time(s) => Session::timed(Duration::from_secs(*s), &word_list()?, self.sampling, self.seed)
+				.with_modifiers(self.modifiers),
+			TestMode::Quote(length) => Session::quote(*length, self.seed),
+			// `from` and `from_str` only make codes for the modes above
+			mode => unreachable!("no test code for {} tests", mode),
+		})
+	}
+}
+
+impl fmt::Display for TestCode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match &self.mode {
+			TestMode::Quote(length) => {
+				let length = match length {
+					QuoteLength::Short => 's',
+					QuoteLength::Medium => 'm',
+					QuoteLength::Long => 'l',
+					QuoteLength::Thicc => 't',
+				};
+				return write!(f, "q{}/{:x}", length, self.seed);
+			}
+			TestMode::Time(s) => write!(f, "t{}", s)?,
+			TestMode::Words(n) => write!(f, "w{}", n)?,
+			// `from` and `from_str` only make codes for the modes above
+			mode => unreachable!("no test code for {} tests", mode),
+		}
+
+		let mut options = String::new();
+		if self.modifiers.punctuation {
+			options += &format!("p{}", percent(self.modifiers.punctuation_rate));
+		}
+		if self.modifiers.numbers {
+			options += &format!("n{}", percent(self.modifiers.numbers_rate));
+		}
+		match self.sampling.weighting {
+			Weighting::Uniform => {},
+			Weighting::Frequency => options.push('f'),
+			Weighting::Zipf => options.push('z'),
+		}
+		if self.sampling.no_repeat > 0 {
+			options += &format!("r{}", self.sampling.no_repeat);
+		}
+		if options.is_empty() {
+			options.push('-');
+		}
+
+		let word_list = self.word_list.as_deref().unwrap_or_default();
+		write!(f, "/{}/{}/{:x}", word_list, options, self.seed)
+	}
+}
+
+// rates are written as percentages, rounded to two decimal places
+fn percent(rate: f64) -> f64 {
+	(rate * 10000.0).round() / 100.0
+}
+
+impl FromStr for TestCode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("'{}' isn't a valid test code", s);
+		let parts: Vec<&str> = s.trim().split('/').collect();
+
+		let (mode, rest) = parts.split_first().ok_or_else(invalid)?;
+		let mut chars = mode.chars();
+		let kind = chars.next().ok_or_else(invalid)?;
+		let length = chars.as_str();
+
+		if kind == 'q' {
+			let length = match length {
+				"s" => QuoteLength::Short,
+				"m" => QuoteLength::Medium,
+				"l" => QuoteLength::Long,
+				"t" => QuoteLength::Thicc,
+				_ => return Err(invalid()),
+			};
+			let [seed] = rest else {
+				return Err(invalid());
+			};
+			return Ok(Self {
+				mode: TestMode::Quote(length),
+				word_list: None,
+				modifiers: Modifiers::default(),
+				sampling: Sampling::default(),
+				seed: u64::from_str_radix(seed, 16).map_err(|_| invalid())?,
+			});
+		}
+
+		let mode = match kind {
+			'w' => TestMode::Words(length.parse().map_err(|_| invalid())?),
+			't' => TestMode::Time(length.parse().map_err(|_| invalid())?),
+			_ => return Err(invalid()),
+		};
+		let [word_list, options, seed] = rest else {
+			return Err(invalid());
+		};
+		if word_list.is_empty() {
+			return Err(invalid());
+		}
+
+		let mut modifiers = Modifiers::default();
+		let mut sampling = Sampling::default();
+		let mut options = options.strip_prefix('-').unwrap_or(options);
+		while let Some(option) = options.chars().next() {
+			if !option.is_ascii_alphabetic() {
+				return Err(invalid());
+			}
+			// each option is a letter, followed by a number for some
+			let value_len = options[1..]
+				.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+				.unwrap_or(options.len() - 1);
+			let value = &options[1..1 + value_len];
+			options = &options[1 + value_len..];
+
+			let rate = || value.parse::<f64>().ok()
+				.map(|percent| percent / 100.0)
+				.filter(|rate| (0.0..=1.0).contains(rate))
+				.ok_or_else(invalid);
+			match option {
+				'p' => {
+					modifiers.punctuation = true;
+					modifiers.punctuation_rate = rate()?;
+				},
+				'n' => {
+					modifiers.numbers = true;
+					modifiers.numbers_rate = rate()?;
+				},
+				'f' if value.is_empty() => sampling.weighting = Weighting::Frequency,
+				'z' if value.is_empty() => sampling.weighting = Weighting::Zipf,
+				'r' => sampling.no_repeat = value.parse().map_err(|_| invalid())?,
+				_ => return Err(invalid()),
+			}
+		}
+
+		Ok(Self {
+			mode,
+			word_list: Some(word_list.to_string()),
+			modifiers,
+			sampling,
+			seed: u64::from_str_radix(seed, 16).map_err(|_| invalid())?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod share_tests {
+	use super::*;
+
+	#[test]
+	fn test_round_trip() {
+		let codes = [
+			"w25/english-1k/-/0",
+			"t60/german/p25n15zr10/1f2e3d4c",
+			"w10/english-1k/n12.5f/ffffffffffffffff",
+			"qt/abc",
+		];
+		for code in codes {
+			assert_eq!(code.parse::<TestCode>().unwrap().to_string(), code);
+		}
+
+		let code: TestCode = "t60/german/p25n15zr10/1f2e3d4c".parse().unwrap();
+		assert_eq!(code.mode, TestMode::Time(60));
+		assert_eq!(code.modifiers.punctuation_rate, 0.25);
+		assert_eq!(code.modifiers.numbers_rate, 0.15);
+		assert_eq!(code.sampling, Sampling { weighting: Weighting::Zipf, no_repeat: 10 });
+		assert_eq!(code.seed, 0x1f2e3d4c);
+
+		for bad in ["", "x25/english-1k/-/0", "w25/english-1k/-", "w25//-/0", "w25/english-1k/-/0/1",
+				"w25/english-1k/p200/0", "w25/english-1k/f3/0", "w25/english-1k/é/0", "qm/xyz"] {
+			assert!(bad.parse::<TestCode>().is_err(), "{}", bad);
+		}
+	}
+
+	#[test]
+	fn test_same_words() {
+		let modifiers = Modifiers { punctuation: true, numbers: true, ..Default::default() };
+		let session = Session::random_with_size(30, &WordList::default(), Sampling::default(), 42)
+			.with_modifiers(modifiers);
+		let code = TestCode::from(&session).unwrap();
+		assert_eq!(code.to_string(), "w30/english-1k/p25n15/2a");
+
+		let code: TestCode = code.to_string().parse().unwrap();
+		let copy = code.session().unwrap();
+
+		let words = |session: &Session| -> Vec<Option<String>> {
+			let words = session.target_words.as_ref().unwrap();
+			(0..30).map(|i| words.get_word_at_frozen(i)).collect()
+		};
+		assert_eq!(words(&copy), words(&session));
+
+		assert!(TestCode::from(&Session::from("some text".to_string())).is_none());
+	}
+}
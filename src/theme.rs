@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+pub const DEFAULT_THEME: &str = "dark";
+
+/// Names of the themes that ship with term-type
+pub const BUILTIN_THEMES: [&str; 4] = ["dark", "light", "high-contrast", "colorblind"];
+
+/// Every style the UI draws with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+	/// correctly typed characters
+	pub correct:	Style,
+	/// mistyped characters
+	pub incorrect:	Style,
+	/// characters typed past the end of a word
+	pub extra:		Style,
+	/// characters that haven't been typed yet
+	pub untyped:	Style,
+	/// the character under the cursor
+	pub caret:		Style,
+	/// the character under the ghost's cursor
+	pub ghost:		Style,
+	pub borders:	Style,
+	/// the app title at the top of the screen
+	pub title:		Style,
+	/// things worth celebrating, like a new personal best
+	pub highlight:	Style,
+	/// error messages and mistakes on the result graph
+	pub error:		Style,
+	/// the wpm line on the result graph
+	pub graph_wpm:	Style,
+	/// the raw wpm line on the result graph
+	pub graph_raw:	Style,
+	/// keys on the heatmap that are rarely missed
+	pub heat_low:	Style,
+	/// keys on the heatmap that are sometimes missed
+	pub heat_mid:	Style,
+	/// keys on the heatmap that are often missed
+	pub heat_high:	Style,
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		Self::dark()
+	}
+}
+
+impl Theme {
+	pub fn dark() -> Self {
+		Self {
+			correct: Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+			incorrect: Style::default().fg(Color::Red)
+				.add_modifier(Modifier::BOLD | Modifier::CROSSED_OUT),
+			extra: Style::default().fg(Color::Red).add_modifier(Modifier::ITALIC),
+			untyped: Style::default(),
+			caret: Style::default().fg(Color::Black).bg(Color::White),
+			ghost: Style::default().fg(Color::Black).bg(Color::DarkGray),
+			borders: Style::default(),
+			title: Style::default(),
+			highlight: Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+			error: Style::default().fg(Color::Red),
+			graph_wpm: Style::default().fg(Color::Yellow),
+			graph_raw: Style::default().fg(Color::DarkGray),
+			heat_low: Style::default().fg(Color::Black).bg(Color::Green),
+			heat_mid: Style::default().fg(Color::Black).bg(Color::Yellow),
+			heat_high: Style::default().fg(Color::Black).bg(Color::Red),
+		}
+	}
+
+	/// For terminals with a light background
+	pub fn light() -> Self {
+		Self {
+			correct: Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
+			incorrect: Style::default().fg(Color::Red)
+				.add_modifier(Modifier::BOLD | Modifier::CROSSED_OUT),
+			extra: Style::default().fg(Color::Magenta).add_modifier(Modifier::ITALIC),
+			untyped: Style::default().fg(Color::DarkGray),
+			caret: Style::default().fg(Color::White).bg(Color::Black),
+			ghost: Style::default().fg(Color::Black).bg(Color::Gray),
+			borders: Style::default().fg(Color::Gray),
+			title: Style::default().fg(Color::Black).add_modifier(Modifier::BOLD),
+			highlight: Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+			error: Style::default().fg(Color::Red),
+			graph_wpm: Style::default().fg(Color::Blue),
+			graph_raw: Style::default().fg(Color::Gray),
+			heat_low: Style::default().fg(Color::Black).bg(Color::LightGreen),
+			heat_mid: Style::default().fg(Color::Black).bg(Color::LightYellow),
+			heat_high: Style::default().fg(Color::White).bg(Color::Red),
+		}
+	}
+
+	/// Bold, bright colours, and mistakes that don't rely on colour alone
+	pub fn high_contrast() -> Self {
+		Self {
+			correct: Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+			incorrect: Style::default().fg(Color::Black).bg(Color::LightRed)
+				.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+			extra: Style::default().fg(Color::Black).bg(Color::LightRed)
+				.add_modifier(Modifier::ITALIC | Modifier::UNDERLINED),
+			untyped: Style::default().fg(Color::Gray),
+			caret: Style::default().fg(Color::Black).bg(Color::LightYellow)
+				.add_modifier(Modifier::BOLD),
+			ghost: Style::default().fg(Color::Black).bg(Color::LightCyan),
+			borders: Style::default().fg(Color::White),
+			title: Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+			highlight: Style::default().fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+			error: Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD),
+			graph_wpm: Style::default().fg(Color::LightYellow),
+			graph_raw: Style::default().fg(Color::White),
+			heat_low: Style::default().fg(Color::Black).bg(Color::White),
+			heat_mid: Style::default().fg(Color::Black).bg(Color::LightYellow)
+				.add_modifier(Modifier::BOLD),
+			heat_high: Style::default().fg(Color::Black).bg(Color::LightRed)
+				.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+		}
+	}
+
+	/// Blue and orange from the Okabe-Ito palette instead of green and red
+	pub fn colorblind() -> Self {
+		let blue = Color::Rgb(0, 114, 178);
+		let orange = Color::Rgb(230, 159, 0);
+		let vermillion = Color::Rgb(213, 94, 0);
+
+		Self {
+			correct: Style::default().fg(blue).add_modifier(Modifier::BOLD),
+			incorrect: Style::default().fg(orange)
+				.add_modifier(Modifier::BOLD | Modifier::CROSSED_OUT),
+			extra: Style::default().fg(vermillion).add_modifier(Modifier::ITALIC),
+			untyped: Style::default(),
+			caret: Style::default().fg(Color::Black).bg(Color::White),
+			ghost: Style::default().fg(Color::Black).bg(Color::Rgb(86, 180, 233)),
+			borders: Style::default(),
+			title: Style::default(),
+			highlight: Style::default().fg(Color::Rgb(240, 228, 66)).add_modifier(Modifier::BOLD),
+			error: Style::default().fg(vermillion),
+			graph_wpm: Style::default().fg(blue),
+			graph_raw: Style::default().fg(Color::DarkGray),
+			heat_low: Style::default().fg(Color::White).bg(blue),
+			heat_mid: Style::default().fg(Color::Black).bg(Color::Rgb(240, 228, 66)),
+			heat_high: Style::default().fg(Color::Black).bg(vermillion),
+		}
+	}
+
+	pub fn builtin(name: &str) -> Option<Self> {
+		match name {
+			"dark" => Some(Self::dark()),
+			"light" => Some(Self::light()),
+			"high-contrast" => Some(Self::high_contrast()),
+			"colorblind" | "colourblind" => Some(Self::colorblind()),
+			_ => None,
+		}
+	}
+
+	/// Looks a theme up by name, user defined themes first
+	pub fn named(name: &str, custom: &HashMap<String, ThemeConfig>) -> Result<Self, String> {
+		if let Some(config) = custom.get(name) {
+			let base_name = config.base.as_deref().unwrap_or(DEFAULT_THEME);
+			let base = Self::builtin(base_name)
+				.ok_or_else(|| format!("theme '{}' is based on unknown theme '{}'", name, base_name))?;
+			return Ok(config.apply(base));
+		}
+
+		Self::builtin(name).ok_or_else(|| {
+			let mut names: Vec<&str> = BUILTIN_THEMES.to_vec();
+			names.extend(custom.keys().map(|k| k.as_str()));
+			format!("unknown theme '{}' (available: {})", name, names.join(", "))
+		})
+	}
+}
+
+/// A user defined theme: changes on top of a built-in one
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+	/// built-in theme to start from, dark if not given
+	pub base:		Option<String>,
+	pub correct:	Option<StyleConfig>,
+	pub incorrect:	Option<StyleConfig>,
+	pub extra:		Option<StyleConfig>,
+	pub untyped:	Option<StyleConfig>,
+	pub caret:		Option<StyleConfig>,
+	pub ghost:		Option<StyleConfig>,
+	pub borders:	Option<StyleConfig>,
+	pub title:		Option<StyleConfig>,
+	pub highlight:	Option<StyleConfig>,
+	pub error:		Option<StyleConfig>,
+	pub graph_wpm:	Option<StyleConfig>,
+	pub graph_raw:	Option<StyleConfig>,
+	pub heat_low:	Option<StyleConfig>,
+	pub heat_mid:	Option<StyleConfig>,
+	pub heat_high:	Option<StyleConfig>,
+}
+
+impl ThemeConfig {
+	fn apply(&self, base: Theme) -> Theme {
+		let apply = |style: &Option<StyleConfig>, base: Style| match style {
+			Some(style) => style.apply(base),
+			None => base,
+		};
+
+		Theme {
+			correct: apply(&self.correct, base.correct),
+			incorrect: apply(&self.incorrect, base.incorrect),
+			extra: apply(&self.extra, base.extra),
+			untyped: apply(&self.untyped, base.untyped),
+			caret: apply(&self.caret, base.caret),
+			ghost: apply(&self.ghost, base.ghost),
+			borders: apply(&self.borders, base.borders),
+			title: apply(&self.title, base.title),
+			highlight: apply(&self.highlight, base.highlight),
+			error: apply(&self.error, base.error),
+			graph_wpm: apply(&self.graph_wpm, base.graph_wpm),
+			graph_raw: apply(&self.graph_raw, base.graph_raw),
+			heat_low: apply(&self.heat_low, base.heat_low),
+			heat_mid: apply(&self.heat_mid, base.heat_mid),
+			heat_high: apply(&self.heat_high, base.heat_high),
+		}
+	}
+}
+
+/// One style in a user defined theme, e.g. `{ fg = "cyan", bold = true }`.
+/// Anything left out is kept from the base theme.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+	pub fg:				Option<Color>,
+	pub bg:				Option<Color>,
+	pub bold:			Option<bool>,
+	pub dim:			Option<bool>,
+	pub italic:			Option<bool>,
+	pub underlined:		Option<bool>,
+	pub crossed_out:	Option<bool>,
+	pub reversed:		Option<bool>,
+}
+
+impl StyleConfig {
+	fn apply(&self, mut style: Style) -> Style {
+		if let Some(fg) = self.fg {
+			style = style.fg(fg);
+		}
+		if let Some(bg) = self.bg {
+			style = style.bg(bg);
+		}
+
+		for (setting, modifier) in [
+			(self.bold, Modifier::BOLD),
+			(self.dim, Modifier::DIM),
+			(self.italic, Modifier::ITALIC),
+			(self.underlined, Modifier::UNDERLINED),
+			(self.crossed_out, Modifier::CROSSED_OUT),
+			(self.reversed, Modifier::REVERSED),
+		] {
+			style = match setting {
+				Some(true) => style.add_modifier(modifier),
+				Some(false) => style.remove_modifier(modifier),
+				None => style,
+			};
+		}
+
+		style
+	}
+}
+
+#[cfg(test)]
+mod theme_tests {
+	use super::*;
+
+	#[test]
+	fn test_named_themes() {
+		let mut custom = HashMap::new();
+		custom.insert("mine".to_string(), ThemeConfig {
+			base: Some("light".to_string()),
+			correct: Some(StyleConfig {
+				fg: Some(Color::Cyan),
+				bold: Some(false),
+				..Default::default()
+			}),
+			..Default::default()
+		});
+
+		for name in BUILTIN_THEMES {
+			assert!(Theme::named(name, &custom).is_ok(), "{}", name);
+		}
+
+		let mine = Theme::named("mine", &custom).unwrap();
+		assert_eq!(mine.correct.fg, Some(Color::Cyan));
+		assert!(!mine.correct.add_modifier.contains(Modifier::BOLD));
+		assert_eq!(mine.incorrect, Theme::light().incorrect);
+
+		assert!(Theme::named("nope", &custom).unwrap_err().contains("mine"));
+
+		custom.get_mut("mine").unwrap().base = Some("nope".to_string());
+		assert!(Theme::named("mine", &custom).is_err());
+	}
+}
@@ -1,15 +1,46 @@
-use std::str::Chars;
+use std::collections::BTreeMap;
+use std::ops::Range;
 
 use ratatui::{
+	style::{Modifier, Style},
 	layout::{Constraint, Layout, Rect},
-	style::{Color, Modifier, Style},
+	symbols::Marker,
 	text::{Span, Line},
-	widgets::{Wrap, Block, Paragraph},
+	widgets::{Axis, Chart, Dataset, GraphType, Block, Paragraph},
 	Frame,
 };
 
 use crate::app::App;
 use crate::app::AppState;
+use crate::app::Session;
+use crate::app::SessionStats;
+use crate::config::ModeName;
+use crate::history::{self, FilterItem, HistoryEntry, HistoryFilter, SortBy, Summary};
+use crate::keystats::{KeyStat, KeyStats};
+use crate::race::{Race, Racer};
+use crate::settings::MenuItem;
+use crate::share::TestCode;
+use crate::theme::Theme;
+
+// lines of words shown while typing, with the cursor on the middle one
+const VISIBLE_LINES: usize = 3;
+// code needs more of its lines on screen to make sense
+const CODE_VISIBLE_LINES: usize = 9;
+
+// keys missed at least this often are drawn with the mid or high heat style
+const HEAT_MID: f32 = 0.03;
+const HEAT_HIGH: f32 = 0.10;
+// indent in columns, then the keys, for each row of the heatmap keyboard
+const KEYBOARD_ROWS: [(usize, &str); 4] = [
+	(0, "`1234567890-="),
+	(2, "qwertyuiop[]\\"),
+	(3, "asdfghjkl;'"),
+	(5, "zxcvbnm,./"),
+];
+const SPACE_BAR_INDENT: usize = 15;
+const SPACE_BAR_WIDTH: usize = 23;
+const HEATMAP_WIDTH: u16 = 56;
+const HEATMAP_HEIGHT: u16 = 9;
 
 pub fn draw(frame: &mut Frame, app: &mut App) {
 	let chunks = Layout::vertical(
@@ -22,132 +53,998 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
 	   	" - Written by Foster Smith ".into(),
 	]);
 	
-	let title_paragraph = Paragraph::new(title.centered())
-		.block(Block::bordered());
+	let title_paragraph = Paragraph::new(title.centered().style(app.theme.title))
+		.block(Block::bordered().border_style(app.theme.borders));
 	frame.render_widget(title_paragraph, chunks[0]);
 	
 	match app.state {
-		AppState::Typing => draw_typing(frame, app, chunks[1]),
-		AppState::Menu => draw_menu(frame, chunks[1]),
+		AppState::Typing => {
+			let mut area = chunks[1];
+			if let Some(race) = app.race.as_ref().filter(|_| app.race_start.is_some()) {
+				let [racers_area, typing_area] = Layout::vertical(
+					[Constraint::Length(race.racers().len() as u16 + 2), Constraint::Min(0)])
+					.areas(area);
+				draw_racers(frame, race, &app.theme, racers_area);
+				area = typing_area;
+			}
+			let block = typing_block(app);
+			let ghost = app.ghost.as_mut().and_then(|ghost| ghost.position(&app.active_session));
+			draw_typing(frame, &mut app.active_session, &app.theme, block, ghost, area);
+		},
+		AppState::Menu => draw_menu(frame, app, chunks[1]),
 		AppState::Stats => draw_stats(frame, app, chunks[1]),
+		AppState::Replays => draw_replays(frame, app, chunks[1]),
+		AppState::Replay => draw_replay(frame, app, chunks[1]),
+		AppState::Lobby => draw_lobby(frame, app, chunks[1]),
+		AppState::History => draw_history(frame, app, chunks[1]),
 	}
 }
 
-fn draw_menu(frame: &mut Frame, area: Rect) {
-	let menu_paragraph = Paragraph::new(
-			Line::from("Press Enter to Start Test").centered()
-		)
-		.block(Block::bordered());
+fn draw_menu(frame: &mut Frame, app: &App, area: Rect) {
+	let hint = if app.quit_pending {
+		format!("Press {} again to quit", app.keys.menu)
+	} else {
+		format!("{} to quit", app.keys.menu)
+	};
+
+	let values: Vec<(MenuItem, bool, String)> = MenuItem::ALL.iter()
+		.map(|item| {
+			let applies = item.applies_to(app.settings.mode);
+			let value = if applies { menu_value(app, *item) } else { "-".to_string() };
+			(*item, applies, value)
+		})
+		.collect();
+	// every row is as wide as the widest so they line up when centered
+	let width = values.iter().map(|(_, _, value)| value.chars().count()).max().unwrap_or(0);
+
+	let mut lines = vec![Line::from("")];
+	for (i, (item, applies, value)) in values.iter().enumerate() {
+		let style = if i == app.menu_item {
+			app.theme.highlight
+		} else if *applies {
+			app.theme.untyped
+		} else {
+			app.theme.untyped.add_modifier(Modifier::DIM)
+		};
+		let text = format!("{:>12}  < {:<width$} >", item.label(), value, width = width);
+		lines.push(Line::from(text).style(style).centered());
+	}
+	lines.push(Line::from(""));
+	lines.push(Line::from("Press Enter to Start Test").centered());
+	lines.push(Line::from("Press p to Replay a Saved Test").style(app.theme.untyped).centered());
+	lines.push(Line::from("Press r for Result History").style(app.theme.untyped).centered());
+	if let Some(error) = &app.menu_error {
+		lines.push(Line::from(error.as_str()).style(app.theme.error).centered());
+	}
+
+	let menu_paragraph = Paragraph::new(lines)
+		.block(Block::bordered()
+			.border_style(app.theme.borders)
+			.title_bottom(Line::from(format!(" up/down choose | left/right change | {} ", hint))
+				.right_aligned()));
 	frame.render_widget(menu_paragraph, area);
 }
 
+// e.g. "60s" for the length of a 60 second test
+fn menu_value(app: &App, item: MenuItem) -> String {
+	let settings = &app.settings;
+	let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+
+	match item {
+		MenuItem::Mode => settings.mode.to_string(),
+		MenuItem::Length => match settings.mode {
+			ModeName::Words | ModeName::Adaptive => format!("{} words", settings.words),
+			ModeName::Time => format!("{}s", settings.time),
+			ModeName::Quote => settings.quote.to_string(),
+			ModeName::Zen if settings.zen_idle == 0 => "until finished".to_string(),
+			ModeName::Zen => format!("until {}s idle", settings.zen_idle),
+			ModeName::Code => settings.code_name(),
+			ModeName::Custom => "-".to_string(),
+		},
+		MenuItem::WordList => settings.word_list_name(),
+		MenuItem::Punctuation => on_off(settings.punctuation),
+		MenuItem::Numbers => on_off(settings.numbers),
+		MenuItem::Indentation if settings.skip_indent => "skipped".to_string(),
+		MenuItem::Indentation => "typed".to_string(),
+		MenuItem::Pace => settings.pace.to_string(),
+		MenuItem::Theme => settings.theme.clone(),
+	}
+}
+
 fn draw_stats(frame: &mut Frame, app: &mut App, area: Rect) {
 	let stats = &app.active_stats;
-	let stats_paragraph = Paragraph::new(vec![
-		Line::from(format!("wpm: {}", stats.wpm)),
-		Line::from(format!("wpm raw: {}", stats.wpm_raw)),
-		Line::from(format!("acc: {}%", stats.acc*(100 as f32))),
-		Line::from(format!("words: {}/{}", stats.word_corr, stats.word_total)),
-		Line::from(format!("chars: {}/{}", stats.char_corr, stats.char_total)),
-		Line::from(format!("test duration (s): {}", stats.duration_s)),
-	])
-	.block(Block::bordered());
-	frame.render_widget(stats_paragraph, area);
-}
-
-fn draw_typing(frame: &mut Frame, app: &mut App, area: Rect) {
-	let cursor_word = app.active_session.get_cursor_word();
-	let cursor_char = app.active_session.get_cursor_char();
-
-	let session = &mut app.active_session;	
-
-	let mut input_spans: Vec<Span> = vec![];
-	let target_words = &mut session.target_words;
-	let words_to_render: usize;
-	let input_len = session.input.len();
-
-	match target_words.len() {
-		Some(len) => words_to_render = len,
-		None => words_to_render = input_len + 10,
-	}
-		
-	for i in 0..words_to_render {
-		let mut char_i = 0;
-
-		let word = target_words
-			.get_word_at(i)
-			.expect("Ran out of words unexpectedly! 
-			Check WordGenerator implementation");
-		let mut typed_chars_opt: Option<Chars> = None;
-		if i < session.input.len() {
-			typed_chars_opt = Option::from(
-				session.input[i].chars()
-			);
-		}
 
-		// Chars in target
-		for ch in word.chars() {
-			let mut typed_char_opt: Option<char> = None;
-			if let Some(typed_chars) = typed_chars_opt.as_mut() {
-				typed_char_opt = typed_chars.next();			
-			}
+	let best_line = if app.is_new_best {
+		Line::from("new personal best!").style(app.theme.highlight)
+	} else {
+		Line::from(format!("personal best: {}",
+			app.previous_best.unwrap_or_default()))
+	};
 
-			let mut style: Style;
-			if typed_char_opt.is_none() {
-				style = Style::default();
-			} else if typed_char_opt.expect("") == ch {
-				style = Style::default().fg(Color::Green)
-					.add_modifier(Modifier::BOLD);
-			} else {
-				style = Style::default().fg(Color::Red)
-					.add_modifier(Modifier::BOLD)
-					.add_modifier(Modifier::CROSSED_OUT);
+	let mode_line = Line::from(format!("mode: {}{}", app.active_session.mode, modifier_names(app)));
+	let mut lines = match stats.acc {
+		Some(acc) => vec![
+			mode_line,
+			Line::from(format!("wpm: {}", stats.wpm)),
+			Line::from(format!("wpm raw: {}", stats.wpm_raw)),
+			Line::from(format!("acc: {}%", acc*100.0)),
+			Line::from(format!("consistency: {}%", stats.consistency*100.0)),
+			Line::from(format!("burst: {} avg / {} best", stats.burst_avg, stats.burst_max)),
+			Line::from(format!("words: {}/{}", stats.word_corr, stats.word_total)),
+			Line::from(format!("chars: {}/{}", stats.char_corr, stats.char_total)),
+			Line::from(format!("test duration (s): {}", stats.duration_s)),
+			best_line,
+		],
+		// zen tests have nothing to be right or wrong about
+		None => vec![
+			mode_line,
+			Line::from(format!("wpm: {}", stats.wpm)),
+			Line::from(format!("chars: {}", stats.char_total)),
+			Line::from(format!("test duration (s): {}", stats.duration_s)),
+			best_line,
+		],
+	};
+	let source = app.active_session.target_words.as_ref().and_then(|words| words.source());
+	if let Some(source) = source {
+		lines.insert(1, Line::from(format!("quote by: {}", source)));
+	}
+	if let Some(ghost) = &app.ghost {
+		let line = match ghost.result(stats) {
+			(true, by) => Line::from(format!("beat the ghost, {}, by {:.1} wpm", ghost, by))
+				.style(app.theme.highlight),
+			(false, by) => Line::from(format!("the ghost, {}, won by {:.1} wpm", ghost, by)),
+		};
+		lines.push(line);
+	}
+	if let Some(code) = TestCode::from(&app.active_session) {
+		lines.push(Line::from(format!("test code: {}", code)));
+	}
+	if let Some(race) = app.race.as_ref().filter(|_| app.race_start.is_some()) {
+		lines.push(Line::from("race results:"));
+		lines.extend(leaderboard(race, &app.theme));
+	}
+	if let Some(error) = &app.history_error {
+		lines.push(Line::from(error.as_str()).style(app.theme.error));
+	}
+
+	let height = (lines.len() as u16 + 2).max(HEATMAP_HEIGHT);
+	let chunks = Layout::vertical(
+		[Constraint::Length(height), Constraint::Min(0)])
+		.split(area);
+	let top = Layout::horizontal(
+		[Constraint::Min(0), Constraint::Length(HEATMAP_WIDTH)])
+		.split(chunks[0]);
+
+	let stats_paragraph = Paragraph::new(lines)
+		.block(Block::bordered().border_style(app.theme.borders));
+	frame.render_widget(stats_paragraph, top[0]);
+
+	draw_key_heatmap(frame, &app.key_stats, &app.theme, top[1]);
+	let hint = " enter menu | p replay |".to_string() + &restart_hint(app);
+	draw_wpm_chart(frame, stats, &app.theme, &hint, chunks[1]);
+}
+
+// e.g. " tab+enter restart | ctrl+r repeat | esc menu "
+fn restart_hint(app: &App) -> String {
+	let keys = &app.keys;
+	format!(" {}+enter restart | {} repeat | {} menu ", keys.restart, keys.repeat, keys.menu)
+}
+
+// e.g. " (punctuation, numbers)", or nothing when no modifiers are on
+fn modifier_names(app: &App) -> String {
+	let modifiers = &app.active_session.modifiers;
+	let mut names = vec![];
+	if modifiers.punctuation {
+		names.push("punctuation");
+	}
+	if modifiers.numbers {
+		names.push("numbers");
+	}
+
+	if names.is_empty() {
+		String::new()
+	} else {
+		format!(" ({})", names.join(", "))
+	}
+}
+
+// each key is drawn 3 columns wide with a gap after it
+fn draw_key_heatmap(frame: &mut Frame, key_stats: &KeyStats, theme: &Theme, area: Rect) {
+	// shifted characters count towards the key they're typed on
+	let mut by_key: BTreeMap<char, KeyStat> = BTreeMap::new();
+	for (c, stat) in &key_stats.keys {
+		by_key.entry(base_key(*c)).or_default().add(stat);
+	}
+
+	let key_style = |key: char| match by_key.get(&key) {
+		Some(stat) if stat.presses() > 0 => match stat.miss_rate() {
+			rate if rate < HEAT_MID => theme.heat_low,
+			rate if rate < HEAT_HIGH => theme.heat_mid,
+			_ => theme.heat_high,
+		},
+		_ => theme.untyped.add_modifier(Modifier::DIM),
+	};
+
+	let mut lines: Vec<Line> = KEYBOARD_ROWS.iter()
+		.map(|(indent, keys)| {
+			let mut spans = vec![Span::from(" ".repeat(*indent))];
+			for key in keys.chars() {
+				spans.push(Span::styled(format!(" {} ", key), key_style(key)));
+				spans.push(Span::from(" "));
 			}
+			Line::from(spans)
+		})
+		.collect();
+	lines.push(Line::from(vec![
+		Span::from(" ".repeat(SPACE_BAR_INDENT)),
+		Span::styled(format!("{:^width$}", "space", width = SPACE_BAR_WIDTH), key_style(' ')),
+	]));
 
-			if i==cursor_word && char_i==cursor_char {
-				style = style.bg(Color::White).fg(Color::Black);
-			}			
+	let mut missed: Vec<(&char, &KeyStat)> = by_key.iter()
+		.filter(|(_, stat)| stat.misses > 0)
+		.collect();
+	missed.sort_by(|a, b| b.1.miss_rate().total_cmp(&a.1.miss_rate()));
+	let missed: Vec<String> = missed.iter()
+		.take(3)
+		.map(|(key, stat)| {
+			let instead: String = stat.top_substitutions(2).iter()
+				.map(|c| key_name(*c))
+				.collect::<Vec<_>>()
+				.join(" ");
+			format!("{} {:.0}% ({})", key_name(**key), stat.miss_rate() * 100.0, instead)
+		})
+		.collect();
 
-			let s: Span = Span::styled(ch.to_string(), style);
-			input_spans.push(s);
+	let mut slow: Vec<(&char, f32)> = by_key.iter()
+		.filter_map(|(key, stat)| stat.avg_latency_ms().map(|ms| (key, ms)))
+		.collect();
+	slow.sort_by(|a, b| b.1.total_cmp(&a.1));
+	let slow: Vec<String> = slow.iter()
+		.take(3)
+		.map(|(key, ms)| format!("{} {:.0}ms", key_name(**key), ms))
+		.collect();
 
-			char_i += 1;
-		}
-		// Overtyped chars
-		if let Some(typed_chars) = typed_chars_opt.as_mut() {
-			let overtyped_str = typed_chars.as_str();
-			let overtyped_span = Span::styled(overtyped_str,
-				Style::default().fg(Color::Red)
-					.add_modifier(Modifier::ITALIC)
-			);
-			input_spans.push(overtyped_span);
-			char_i += overtyped_str.len();
-		}
+	lines.push(Line::from(format!("most missed: {}", missed.join(", "))));
+	lines.push(Line::from(format!("slowest: {}", slow.join(", "))));
 
-		if i==cursor_word && char_i == cursor_char {
-			input_spans.push(Span::styled(" ", 
-				Style::default().bg(Color::White)));
-		} else {
-			input_spans.push(Span::from(" "));
-		}
+	let heatmap = Paragraph::new(lines)
+		.block(Block::bordered()
+			.border_style(theme.borders)
+			.title(" keys, all tests "));
+	frame.render_widget(heatmap, area);
+}
+
+// the key on a US keyboard that types `c`
+fn base_key(c: char) -> char {
+	const SHIFTED: &str = "~!@#$%^&*()_+{}|:\"<>?";
+	const UNSHIFTED: &str = "`1234567890-=[]\\;',./";
+
+	match SHIFTED.chars().position(|shifted| shifted == c) {
+		Some(i) => UNSHIFTED.chars().nth(i).unwrap_or(c),
+		None => c.to_ascii_lowercase(),
+	}
+}
+
+fn key_name(c: char) -> String {
+	match c {
+		' ' => "space".to_string(),
+		'\n' => "enter".to_string(),
+		c => c.to_string(),
+	}
+}
+
+fn draw_wpm_chart(frame: &mut Frame, stats: &SessionStats, theme: &Theme, hint: &str, area: Rect) {
+	let wpm_points: Vec<(f64, f64)> = stats.timeline.iter()
+		.map(|s| (s.time_s, s.wpm as f64))
+		.collect();
+	let raw_points: Vec<(f64, f64)> = stats.timeline.iter()
+		.map(|s| (s.time_s, s.wpm_raw as f64))
+		.collect();
+	// errors sit on the wpm line so it's clear where they slowed things down
+	let error_points: Vec<(f64, f64)> = stats.timeline.iter()
+		.filter(|s| s.errors > 0)
+		.map(|s| (s.time_s, s.wpm as f64))
+		.collect();
+
+	let max_wpm = stats.timeline.iter()
+		.map(|s| s.wpm.max(s.wpm_raw) as f64)
+		.fold(0.0, f64::max);
+	// round up to the next 10 so the top label is a whole number
+	let y_max = ((max_wpm / 10.0).ceil() * 10.0).max(10.0);
+	let x_max = stats.duration_s.max(1.0);
+
+	let datasets = vec![
+		Dataset::default()
+			.name("raw")
+			.marker(Marker::Braille)
+			.graph_type(GraphType::Line)
+			.style(theme.graph_raw)
+			.data(&raw_points),
+		Dataset::default()
+			.name("wpm")
+			.marker(Marker::Braille)
+			.graph_type(GraphType::Line)
+			.style(theme.graph_wpm)
+			.data(&wpm_points),
+		Dataset::default()
+			.name("errors")
+			.marker(Marker::Dot)
+			.graph_type(GraphType::Scatter)
+			.style(theme.error)
+			.data(&error_points),
+	];
+
+	let chart = Chart::new(datasets)
+		.block(Block::bordered()
+			.border_style(theme.borders)
+			.title_bottom(Line::from(hint).right_aligned()))
+		.x_axis(Axis::default()
+			.title("seconds")
+			.bounds([0.0, x_max])
+			.labels(["0".to_string(), format!("{:.0}", x_max)]))
+		.y_axis(Axis::default()
+			.title("wpm")
+			.bounds([0.0, y_max])
+			.labels(["0".to_string(), format!("{:.0}", y_max / 2.0), format!("{:.0}", y_max)]));
+
+	frame.render_widget(chart, area);
+}
+
+// e.g. " wpm 72 | raw 80 | acc 96% | 12/25 ", or " wpm 72 | 12 " in zen mode
+fn live_stats_title(session: &Session) -> String {
+	let live = session.live_stats();
+	if session.is_zen() {
+		return format!(" wpm {:.0} | {} ", live.wpm, live.words_done);
+	}
+
+	let progress = match live.words_total {
+		Some(total) => format!("{}/{}", live.words_done, total),
+		None => live.words_done.to_string(),
+	};
+
+	format!(" wpm {:.0} | raw {:.0} | acc {:.0}% | {} ",
+		live.wpm, live.wpm_raw, live.acc * 100.0, progress)
+}
+
+// the border around the test being typed, with the clock and hints
+fn typing_block(app: &App) -> Block<'static> {
+	let session = &app.active_session;
+	let mut hint = restart_hint(app);
+	// races can't be restarted or repeated, only left
+	if app.race_start.is_some() {
+		hint = format!(" {} leave ", app.keys.menu);
+	} else if session.is_zen() {
+		hint = format!(" {} finish |{}", app.keys.finish, hint);
 	}
 
-	let mut age_opt = session.get_age_s();
 	let mut bottom_title_string = "".to_string();
 	
-	if let Some(age_f64) = age_opt.take() {
+	// timed tests count down, everything else counts up
+	if let Some(left_f64) = session.get_time_left_s() {
+		bottom_title_string = (left_f64.ceil() as i64).to_string();
+	} else if let Some(age_f64) = session.get_age_s() {
 		bottom_title_string = (age_f64 as i64).to_string();
 	}
 	
-	let temp_line = Line::from(input_spans);
+	let mut block = Block::bordered()
+		.border_style(app.theme.borders)
+		.title_bottom(bottom_title_string)
+		.title_bottom(Line::from(hint).right_aligned());
+
+	if let Some(left) = app.countdown() {
+		block = block.title_top(Line::from(format!(" starting in {} ", left.as_secs() + 1))
+			.style(app.theme.highlight)
+			.centered());
+	} else if app.show_live_stats {
+		block = block.title_top(live_stats_title(session));
+	}
+	block
+}
+
+// a progress bar for each racer, e.g. "alex  [██████····]  72 wpm"
+fn draw_racers(frame: &mut Frame, race: &Race, theme: &Theme, area: Rect) {
+	let block = Block::bordered()
+		.border_style(theme.borders)
+		.title_top(" racers ");
+	let inner = block.inner(area);
+	let name_width = race.racers().iter().map(|racer| racer.name.chars().count()).max().unwrap_or(0);
+	// the name, the brackets and the speed and place after the bar
+	let bar_width = (inner.width as usize).saturating_sub(name_width + 22).max(1);
+
+	let lines: Vec<Line> = race.racers().iter()
+		.enumerate()
+		.map(|(i, racer)| {
+			let filled = ((racer.progress.done.clamp(0.0, 1.0) * bar_width as f32) as usize).min(bar_width);
+			let status = match racer.place {
+				Some(place) => ordinal(place),
+				None if racer.left => "left".to_string(),
+				None => String::new(),
+			};
+			let text = format!(" {:<name_width$}  [{}{}] {:>4.0} wpm  {}", racer.name,
+				"█".repeat(filled), "·".repeat(bar_width - filled), racer.progress.wpm, status);
+			let style = if i == race.you() { theme.highlight } else { theme.untyped };
+			Line::from(text).style(style)
+		})
+		.collect();
+
+	frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// racers in the order they finished, then everyone still going
+fn leaderboard(race: &Race, theme: &Theme) -> Vec<Line<'static>> {
+	let mut racers: Vec<(usize, &Racer)> = race.racers().iter().enumerate().collect();
+	racers.sort_by_key(|(_, racer)| racer.place.unwrap_or(usize::MAX));
+
+	racers.into_iter()
+		.map(|(i, racer)| {
+			let text = match (racer.place, racer.progress.acc) {
+				(Some(place), acc) => format!("  {} {}: {:.0} wpm, {:.0}% acc", ordinal(place), racer.name,
+					racer.progress.wpm, acc.unwrap_or(1.0) * 100.0),
+				(None, _) if racer.left => format!("  {} left the race", racer.name),
+				(None, _) => format!("  {} is still racing ({:.0}%)", racer.name, racer.progress.done * 100.0),
+			};
+			let style = if i == race.you() { theme.highlight } else { theme.untyped };
+			Line::from(text).style(style)
+		})
+		.collect()
+}
+
+// e.g. "1st", "22nd"
+fn ordinal(n: usize) -> String {
+	let suffix = match (n % 10, n % 100) {
+		(_, 11..=13) => "th",
+		(1, _) => "st",
+		(2, _) => "nd",
+		(3, _) => "rd",
+		_ => "th",
+	};
+	format!("{}{}", n, suffix)
+}
+
+fn draw_lobby(frame: &mut Frame, app: &App, area: Rect) {
+	let Some(race) = &app.race else {
+		return;
+	};
+
+	let mut lines = vec![
+		Line::from(""),
+		Line::from(race.status()).centered(),
+		Line::from(""),
+	];
+	for (i, racer) in race.racers().iter().enumerate() {
+		let mut name = racer.name.clone();
+		if i == race.you() {
+			name += " (you)";
+		} else if racer.left {
+			name += " (left)";
+		}
+		let style = if racer.left { app.theme.untyped.add_modifier(Modifier::DIM) } else { app.theme.untyped };
+		lines.push(Line::from(name).style(style).centered());
+	}
+	lines.push(Line::from(""));
+	if race.is_host() {
+		let settings = &app.settings;
+		lines.push(Line::from(format!("next race: {} ({})", settings.mode,
+			menu_value(app, MenuItem::Length))).centered());
+		lines.push(Line::from("Press Enter to Start the Race").centered());
+	} else {
+		lines.push(Line::from("Waiting for the host to start the race").centered());
+	}
+	if let Some(error) = &app.race_error {
+		lines.push(Line::from(error.as_str()).style(app.theme.error).centered());
+	}
+
+	let hint = match race.is_host() {
+		true => format!(" enter start | {} menu ", app.keys.menu),
+		false => format!(" {} menu ", app.keys.menu),
+	};
+	let lobby_paragraph = Paragraph::new(lines)
+		.block(Block::bordered()
+			.border_style(app.theme.borders)
+			.title_top(" lobby ")
+			.title_bottom(Line::from(hint).right_aligned()));
+	frame.render_widget(lobby_paragraph, area);
+}
+
+// the words of `session` as typed so far, inside `block`, with the ghost
+// caret at the word and character given. Tests and their replays are drawn
+// the same way.
+fn draw_typing(frame: &mut Frame, session: &mut Session, theme: &Theme, block: Block,
+	ghost: Option<(usize, usize)>, area: Rect) {
+	let cursor_word = session.get_cursor_word();
+	let cursor_char = session.get_cursor_char();
+	let visible_lines = if session.is_code() { CODE_VISIBLE_LINES } else { VISIBLE_LINES };
+
+	let inner = block.inner(area);
+	frame.render_widget(block, area);
+
+	// a column of space on either side, and the lines in the middle
+	let [_, text_area, _] = Layout::horizontal(
+		[Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)])
+		.areas(inner);
+	let [_, text_area, _] = Layout::vertical(
+		[Constraint::Fill(1), Constraint::Length(visible_lines as u16), Constraint::Fill(1)])
+		.areas(text_area);
+	let width = text_area.width as usize;
+
+	// words are laid out from the start every frame, so lines never
+	// reflow as the test goes on. Enough words are generated to fill the
+	// line after the cursor's.
+	let mut words: Vec<Vec<Span>> = vec![];
+	let mut widths: Vec<usize> = vec![];
+	let mut line_ends: Vec<bool> = vec![];
+	let mut lines: Vec<Range<usize>> = vec![];
+	loop {
+		let i = words.len();
+		// zen mode has no target, so what was typed is shown as it is
+		let word = match &mut session.target_words {
+			Some(target_words) => target_words.get_word_at(i),
+			None => session.input.get(i).cloned(),
+		};
+		let Some(word) = word else {
+			lines = wrap_words(&widths, &line_ends, width);
+			break;
+		};
+		let typed = session.input.get(i).map(|typed| typed.as_str());
+		let caret = (i == cursor_word).then_some(cursor_char);
+		let ghost_caret = ghost.filter(|(word, _)| *word == i).map(|(_, char)| char);
+		let line_end = session.ends_line(i);
+		let spans = word_spans(&word, typed, Carets { caret, ghost: ghost_caret }, line_end, theme);
+		widths.push(spans.iter().map(|span| span.width()).sum());
+		line_ends.push(line_end);
+		words.push(spans);
+
+		if i < cursor_word {
+			continue;
+		}
+		// the last visible line is full once another one starts
+		lines = wrap_words(&widths, &line_ends, width);
+		if let Some(last_line) = cursor_line(&lines, cursor_word)
+			.map(|line| first_line(line, visible_lines) + visible_lines)
+			&& lines.len() > last_line {
+			lines.truncate(last_line);
+			break;
+		}
+	}
+
+	let top_line = first_line(cursor_line(&lines, cursor_word).unwrap_or(0), visible_lines);
+	let visible: Vec<Line> = lines.iter()
+		.skip(top_line)
+		.take(visible_lines)
+		.map(|range| Line::from(words[range.clone()].concat()))
+		.collect();
+
+	frame.render_widget(Paragraph::new(visible), text_area);
+}
+
+fn draw_replay(frame: &mut Frame, app: &mut App, area: Rect) {
+	let Some(replay) = &mut app.replay else {
+		return;
+	};
+
+	let state = if replay.is_done() {
+		"done".to_string()
+	} else if replay.paused {
+		"paused".to_string()
+	} else {
+		format!("{}x", replay.speed)
+	};
+	let block = Block::bordered()
+		.border_style(app.theme.borders)
+		.title_top(format!(" replay: {} | {} ", replay.session.mode, state))
+		.title_bottom(format!("{:.1}s | {}/{} keys", replay.clock_s(), replay.played, replay.len()))
+		.title_bottom(Line::from(format!(" space pause | left/right step | up/down {}x speed | {} back ",
+			replay.speed, app.keys.menu)).right_aligned());
+
+	draw_typing(frame, &mut replay.session, &app.theme, block, None, area);
+}
+
+fn draw_replays(frame: &mut Frame, app: &App, area: Rect) {
+	let entries = app.replayable();
+	let block = Block::bordered()
+		.border_style(app.theme.borders)
+		.title_top(" saved tests, times in UTC ")
+		.title_bottom(Line::from(format!(" up/down choose | enter replay | {} menu ", app.keys.menu))
+			.right_aligned());
+
+	if entries.is_empty() {
+		let empty = Paragraph::new(Line::from("No saved tests to replay yet").centered())
+			.block(block);
+		frame.render_widget(empty, area);
+		return;
+	}
+
+	// the list scrolls to keep the highlighted test on screen
+	let height = block.inner(area).height.max(1) as usize;
+	let first = app.replay_item.saturating_sub(height - 1);
+	let lines: Vec<Line> = entries.iter()
+		.enumerate()
+		.skip(first)
+		.take(height)
+		.map(|(i, entry)| {
+			let acc = entry.stats.acc.map_or("-".to_string(), |acc| format!("{:.0}%", acc * 100.0));
+			let text = format!(" {}  {:<16} {:>4.0} wpm {:>5}", date_time(entry.timestamp),
+				entry.mode.to_string(), entry.stats.wpm, acc);
+			let style = if i == app.replay_item { app.theme.highlight } else { app.theme.untyped };
+			Line::from(text).style(style)
+		})
+		.collect();
+
+	frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// wide enough for the filter labels and most values
+const FILTERS_WIDTH: u16 = 40;
+
+// the filters and averages on top, the trend below and the results
+// themselves at the bottom
+fn draw_history(frame: &mut Frame, app: &App, area: Rect) {
+	let now = history::now();
+	let filter = &app.history_filter;
+	let entries = app.history.filtered(filter, now);
+	// averages and the trend go by when the tests were taken, whatever the sort
+	let oldest_first = app.history.filtered(&HistoryFilter { sort: SortBy::Oldest, ..filter.clone() }, now);
+
+	let [top, chart_area, list_area] = Layout::vertical(
+		[Constraint::Length(FilterItem::ALL.len() as u16 + 2), Constraint::Fill(1), Constraint::Fill(1)])
+		.areas(area);
+	let [filter_area, summary_area] = Layout::horizontal(
+		[Constraint::Length(FILTERS_WIDTH), Constraint::Min(0)])
+		.areas(top);
+
+	let values: Vec<(FilterItem, bool, String)> = FilterItem::ALL.iter()
+		.map(|item| {
+			let applies = item.applies_to(filter);
+			let value = if applies { filter_value(app, *item) } else { "-".to_string() };
+			(*item, applies, value)
+		})
+		.collect();
+	let width = values.iter().map(|(_, _, value)| value.chars().count()).max().unwrap_or(0);
+	let filter_lines: Vec<Line> = values.iter()
+		.enumerate()
+		.map(|(i, (item, applies, value))| {
+			let style = if i == app.filter_item && app.result_item.is_none() {
+				app.theme.highlight
+			} else if *applies {
+				app.theme.untyped
+			} else {
+				app.theme.untyped.add_modifier(Modifier::DIM)
+			};
+			Line::from(format!("{:>10}  < {:<width$} >", item.label(), value, width = width)).style(style)
+		})
+		.collect();
+	frame.render_widget(Paragraph::new(filter_lines)
+		.block(Block::bordered().border_style(app.theme.borders).title_top(" filters ")), filter_area);
+
+	// the last 100 are only worth a line when there are more than 10
+	let mut summary_lines = vec![];
+	for n in [10, 100] {
+		if n == 100 && oldest_first.len() <= 10 {
+			break;
+		}
+		let newest: Vec<&HistoryEntry> = oldest_first.iter().rev().take(n).copied().collect();
+		if let Some(summary) = Summary::from(&newest) {
+			summary_lines.push(summary_line(&summary));
+		}
+	}
+	if summary_lines.is_empty() {
+		summary_lines.push(Line::from(" no results yet"));
+	}
+	frame.render_widget(Paragraph::new(summary_lines)
+		.block(Block::bordered().border_style(app.theme.borders).title_top(" averages ")), summary_area);
+
+	draw_trend_chart(frame, &oldest_first, &app.theme, chart_area);
+
 	let block = Block::bordered()
-		.title_bottom(bottom_title_string);
+		.border_style(app.theme.borders)
+		.title_top(format!(" {} results, times in UTC ", entries.len()))
+		.title_bottom(Line::from(format!(" up/down choose | left/right change | enter replay | {} menu ",
+			app.keys.menu)).right_aligned());
+	// the list scrolls to keep the highlighted result on screen
+	let height = block.inner(list_area).height.max(1) as usize;
+	let first = app.result_item.map_or(0, |i| i.saturating_sub(height - 1));
+	let list_lines: Vec<Line> = entries.iter()
+		.enumerate()
+		.skip(first)
+		.take(height)
+		.map(|(i, entry)| {
+			let stats = &entry.stats;
+			let acc = stats.acc.map_or("-".to_string(), |acc| format!("{:.0}%", acc * 100.0));
+			let consistency = match entry.has_full_stats() {
+				true => format!("{:.0}%", stats.consistency * 100.0),
+				false => "-".to_string(),
+			};
+			Line::from(format!(" {}  {:<16} {:<12} {:>4.0} wpm {:>5} acc {:>5} consistency",
+				date_time(entry.timestamp), entry.mode.to_string(),
+				entry.word_list.as_deref().unwrap_or("-"), stats.wpm, acc, consistency))
+				.style(if app.result_item == Some(i) { app.theme.highlight } else { app.theme.untyped })
+		})
+		.collect();
+	frame.render_widget(Paragraph::new(list_lines).block(block), list_area);
+}
+
+// e.g. "all" for a filter that's off, or "last week"
+fn filter_value(app: &App, item: FilterItem) -> String {
+	let filter = &app.history_filter;
+	let all = "all".to_string();
 
-	let typing_paragraph = 
-		Paragraph::new(temp_line.centered())
-		.block(block)
-		.wrap(Wrap { trim: true });
+	match item {
+		FilterItem::Sort => filter.sort.to_string(),
+		FilterItem::Mode => filter.mode.map_or(all, |mode| mode.to_string()),
+		FilterItem::Length => filter.length.as_ref().map_or(all, |length| length.to_string()),
+		FilterItem::WordList => filter.word_list.clone().unwrap_or(all),
+		FilterItem::Dates => match filter.days {
+			None => "all time".to_string(),
+			Some(1) => "last day".to_string(),
+			Some(7) => "last week".to_string(),
+			Some(30) => "last month".to_string(),
+			Some(365) => "last year".to_string(),
+			Some(days) => format!("last {} days", days),
+		},
+	}
+}
 
-	frame.render_widget(typing_paragraph, area);
+// e.g. " last 10: 85 wpm avg, 102 best | 96% acc avg, 100% best"
+fn summary_line(summary: &Summary) -> Line<'static> {
+	let mut text = format!(" last {}: {:.0} wpm avg, {:.0} best", summary.tests, summary.avg_wpm, summary.best_wpm);
+	if let (Some(avg), Some(best)) = (summary.avg_acc, summary.best_acc) {
+		text += &format!(" | {:.0}% acc avg, {:.0}% best", avg * 100.0, best * 100.0);
+	}
+	Line::from(text)
 }
 
+// how many tests the trend line averages over
+const TREND_WINDOW: usize = 10;
+
+// every result's wpm in the order they were taken, and the average of the
+// last few at each one to show the trend
+fn draw_trend_chart(frame: &mut Frame, entries: &[&HistoryEntry], theme: &Theme, area: Rect) {
+	let wpm_points: Vec<(f64, f64)> = entries.iter()
+		.enumerate()
+		.map(|(i, entry)| (i as f64, entry.stats.wpm as f64))
+		.collect();
+	let trend_points: Vec<(f64, f64)> = (0..entries.len())
+		.map(|i| {
+			let window = &entries[(i + 1).saturating_sub(TREND_WINDOW)..=i];
+			let avg = window.iter().map(|entry| entry.stats.wpm as f64).sum::<f64>() / window.len() as f64;
+			(i as f64, avg)
+		})
+		.collect();
+
+	let max_wpm = entries.iter().map(|entry| entry.stats.wpm as f64).fold(0.0, f64::max);
+	let y_max = ((max_wpm / 10.0).ceil() * 10.0).max(10.0);
+	let x_max = (entries.len().saturating_sub(1) as f64).max(1.0);
+	// the date part of the first and last tests
+	let date = |entry: Option<&&HistoryEntry>| entry
+		.map_or(String::new(), |entry| date_time(entry.timestamp)[..10].to_string());
+
+	let datasets = vec![
+		Dataset::default()
+			.name("wpm")
+			.marker(Marker::Dot)
+			.graph_type(GraphType::Scatter)
+			.style(theme.graph_raw)
+			.data(&wpm_points),
+		Dataset::default()
+			.name(format!("average of {}", TREND_WINDOW))
+			.marker(Marker::Braille)
+			.graph_type(GraphType::Line)
+			.style(theme.graph_wpm)
+			.data(&trend_points),
+	];
+
+	let chart = Chart::new(datasets)
+		.block(Block::bordered()
+			.border_style(theme.borders)
+			.title_top(" trend "))
+		.x_axis(Axis::default()
+			.title("tests")
+			.bounds([0.0, x_max])
+			.labels([date(entries.first()), date(entries.last())]))
+		.y_axis(Axis::default()
+			.title("wpm")
+			.bounds([0.0, y_max])
+			.labels(["0".to_string(), format!("{:.0}", y_max / 2.0), format!("{:.0}", y_max)]));
+
+	frame.render_widget(chart, area);
+}
+
+// e.g. "2024-03-09 17:45" (UTC) for seconds since the unix epoch
+fn date_time(timestamp: u64) -> String {
+	let days = (timestamp / 86400) as i64;
+	let minutes = timestamp % 86400 / 60;
+
+	// days to a civil date, from Howard Hinnant's date algorithms
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + (month <= 2) as i64;
+
+	format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, minutes / 60, minutes % 60)
+}
+
+fn cursor_line(lines: &[Range<usize>], cursor_word: usize) -> Option<usize> {
+	lines.iter().position(|line| line.contains(&cursor_word))
+}
+
+// the caret stays on the middle line once past the first
+fn first_line(cursor_line: usize, visible_lines: usize) -> usize {
+	cursor_line.saturating_sub(visible_lines / 2)
+}
+
+// where in a word the carets are, if they're in it
+struct Carets {
+	caret:	Option<usize>,
+	ghost:	Option<usize>,
+}
+
+impl Carets {
+	// the user's caret is drawn over the ghost's
+	fn style(&self, char_i: usize, theme: &Theme) -> Option<Style> {
+		if self.caret == Some(char_i) {
+			Some(theme.caret)
+		} else if self.ghost == Some(char_i) {
+			Some(theme.ghost)
+		} else {
+			None
+		}
+	}
+}
+
+// one word as typed so far, followed by the space after it, or a mark for
+// the Enter that ends its line
+fn word_spans<'a>(word: &str, typed: Option<&'a str>, carets: Carets, line_end: bool, theme: &Theme)
+	-> Vec<Span<'a>> {
+	let mut spans = vec![];
+	let mut typed_chars = typed.map(|typed| typed.chars());
+	let mut char_i = 0;
+
+	// Chars in target
+	for ch in word.chars() {
+		let typed_char = typed_chars.as_mut().and_then(|chars| chars.next());
+
+		let mut style = match typed_char {
+			None => theme.untyped,
+			Some(typed_char) if typed_char == ch => theme.correct,
+			Some(_) => theme.incorrect,
+		};
+
+		if let Some(caret) = carets.style(char_i, theme) {
+			style = style.patch(caret);
+		}
+
+		spans.push(Span::styled(ch.to_string(), style));
+		char_i += 1;
+	}
+	// Overtyped chars
+	if let Some(chars) = typed_chars {
+		let overtyped = chars.as_str();
+		if !overtyped.is_empty() {
+			spans.push(Span::styled(overtyped, theme.extra));
+			char_i += overtyped.chars().count();
+		}
+	}
+
+	let (gap, style) = if line_end { ("↵", theme.untyped) } else { (" ", Style::default()) };
+	match carets.style(char_i, theme) {
+		Some(caret) => spans.push(Span::styled(gap, style.patch(caret))),
+		None => spans.push(Span::styled(gap, style)),
+	}
+	spans
+}
+
+/// Splits words (given by width, each including its trailing space) into
+/// lines no wider than `width`. A word is never split: one that is wider
+/// than a whole line gets a line to itself. Words marked in `line_ends`
+/// always end their line.
+fn wrap_words(widths: &[usize], line_ends: &[bool], width: usize) -> Vec<Range<usize>> {
+	let mut lines = vec![];
+	let mut start = 0;
+	let mut line_width = 0;
+
+	for (i, &word_width) in widths.iter().enumerate() {
+		// the trailing space is allowed to hang off the end of a line
+		if i > start && line_width + word_width - 1 > width {
+			lines.push(start..i);
+			start = i;
+			line_width = 0;
+		}
+		line_width += word_width;
+		if line_ends.get(i).copied().unwrap_or(false) {
+			lines.push(start..i + 1);
+			start = i + 1;
+			line_width = 0;
+		}
+	}
+	if start < widths.len() {
+		lines.push(start..widths.len());
+	}
+	lines
+}
+
+#[cfg(test)]
+mod ui_tests {
+	use super::*;
+
+	#[test]
+	fn test_wrap_words() {
+		// "aaa bb cccc d " in a width of 7
+		let lines = wrap_words(&[4, 3, 5, 2], &[], 7);
+		assert_eq!(lines, vec![0..2, 2..4]);
+
+		// the trailing space doesn't count against the width
+		assert_eq!(wrap_words(&[4, 4], &[], 7), vec![0..2]);
+		assert_eq!(wrap_words(&[4, 5], &[], 7), vec![0..1, 1..2]);
+		// too long for any line, so it goes on its own
+		assert_eq!(wrap_words(&[2, 20, 2], &[], 10), vec![0..1, 1..2, 2..3]);
+		assert!(wrap_words(&[], &[], 10).is_empty());
+
+		// lines of code end where they do in the code, blank ones included
+		let line_ends = [false, true, true, false, false];
+		assert_eq!(wrap_words(&[2, 2, 1, 3, 3], &line_ends, 20), vec![0..2, 2..3, 3..5]);
+		assert_eq!(wrap_words(&[2, 2, 1, 3, 3], &line_ends, 4), vec![0..2, 2..3, 3..4, 4..5]);
+	}
+
+	#[test]
+	fn test_draw_typing() {
+		use ratatui::{backend::TestBackend, Terminal};
+		use crate::wordlist::{Sampling, WordList};
+
+		// a 3 word test shows its 3 words and nothing after them
+		let mut session = Session::random_with_size(3, &WordList::default(), Sampling::default(), 1);
+		let mut terminal = Terminal::new(TestBackend::new(80, 7)).unwrap();
+		terminal.draw(|frame| {
+			let area = frame.area();
+			draw_typing(frame, &mut session, &Theme::default(), Block::bordered(), None, area);
+		}).unwrap();
+
+		let drawn: String = terminal.backend().buffer().content().iter()
+			.map(|cell| cell.symbol())
+			.filter(|symbol| !"│─┌┐└┘".contains(*symbol))
+			.collect();
+		let target = session.target_words.as_ref().unwrap();
+		let words: Vec<String> = (0..3).filter_map(|i| target.get_word_at_frozen(i)).collect();
+		assert_eq!(drawn.split_whitespace().collect::<Vec<_>>(), words);
+	}
+
+	#[test]
+	fn test_word_spans() {
+		// the caret sits after the overtyped 'é', which takes two bytes
+		let theme = Theme::default();
+		let carets = Carets { caret: Some(4), ghost: None };
+		let spans = word_spans("caf", Some("café"), carets, false, &theme);
+		assert_eq!(spans.len(), 5);
+		assert_eq!(spans[3].content, "é");
+		assert_eq!(spans[4].style, Style::default().patch(theme.caret));
+	}
+
+	#[test]
+	fn test_ordinal() {
+		let ordinals: Vec<String> = [1, 2, 3, 4, 11, 12, 13, 21, 22, 101, 112].into_iter().map(ordinal).collect();
+		assert_eq!(ordinals, ["1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "101st", "112th"]);
+	}
+
+	#[test]
+	fn test_date_time() {
+		assert_eq!(date_time(0), "1970-01-01 00:00");
+		assert_eq!(date_time(951782400 + 3600 + 120), "2000-02-29 01:02");
+		assert_eq!(date_time(1710006300), "2024-03-09 17:45");
+	}
+
+	#[test]
+	fn test_base_key() {
+		assert_eq!(base_key('A'), 'a');
+		assert_eq!(base_key('!'), '1');
+		assert_eq!(base_key('"'), '\'');
+		assert_eq!(base_key('|'), '\\');
+		assert_eq!(base_key(';'), ';');
+		for (_, keys) in KEYBOARD_ROWS {
+			assert!(keys.chars().all(|key| base_key(key) == key));
+		}
+	}
+}
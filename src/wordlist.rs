@@ -0,0 +1,247 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::paths;
+
+static ENGLISH_1K: &str=include_str!("english-1k.txt");
+
+pub const DEFAULT_LANGUAGE: &str = "english-1k";
+
+const LANGUAGES_DIR: &str = "languages";
+
+/// How steeply the chance of a word falls with its rank in a Zipf curve
+const ZIPF_EXPONENT: f64 = 1.0;
+
+/// How random words are picked from a list
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Weighting {
+	/// every word is as likely as any other
+	#[default]
+	Uniform,
+	/// in proportion to the counts in the list, or along a Zipf curve for
+	/// lists without counts
+	Frequency,
+	/// along a Zipf curve, by rank from most to least common
+	Zipf,
+}
+
+impl fmt::Display for Weighting {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			Weighting::Uniform => "uniform",
+			Weighting::Frequency => "frequency",
+			Weighting::Zipf => "zipf",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+impl FromStr for Weighting {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"uniform" => Ok(Weighting::Uniform),
+			"frequency" => Ok(Weighting::Frequency),
+			"zipf" => Ok(Weighting::Zipf),
+			_ => Err(format!("unknown weighting '{}' (expected uniform, frequency or zipf)", s)),
+		}
+	}
+}
+
+/// Options for drawing random words
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sampling {
+	pub weighting:	Weighting,
+	/// a word can't come up again within this many words of itself, 0 to
+	/// allow repeats
+	pub no_repeat:	usize,
+}
+
+/// The words random tests are drawn from, one word per line, optionally
+/// followed by a tab and how common the word is
+#[derive(Clone, Debug, PartialEq)]
+pub struct WordList {
+	pub name:	String,
+	pub words:	Vec<String>,
+	/// how often each word occurs, if the list says
+	pub counts:	Option<Vec<u64>>,
+}
+
+impl WordList {
+	pub fn default() -> Self {
+		Self::parse(DEFAULT_LANGUAGE, ENGLISH_1K)
+			.expect("bundled word list is invalid")
+	}
+
+	pub fn from_file(path: &Path) -> io::Result<Self> {
+		let contents = fs::read_to_string(path)
+			.map_err(|e| io::Error::new(e.kind(),
+				format!("couldn't read word list '{}': {}", path.display(), e)))?;
+
+		let name = path.file_stem()
+			.map_or("custom".to_string(), |stem| stem.to_string_lossy().into_owned());
+
+		Self::parse(&name, &contents)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+				format!("word list '{}' {}", path.display(), e)))
+	}
+
+	/// Finds a list by name: the bundled one, or one installed as
+	/// `<name>.txt` in the languages directory
+	pub fn installed(name: &str) -> io::Result<Self> {
+		if name == DEFAULT_LANGUAGE {
+			return Ok(Self::default());
+		}
+
+		// names can't be used to reach files outside the languages directory
+		if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				format!("'{}' isn't a valid language name", name)));
+		}
+
+		let dir = languages_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+			"couldn't find a data directory to look for languages in"))?;
+
+		let path = dir.join(format!("{}.txt", name));
+		if !path.is_file() {
+			return Err(io::Error::new(io::ErrorKind::NotFound, format!(
+				"language '{}' isn't installed (expected a word list at '{}')",
+				name, path.display())));
+		}
+
+		Self::from_file(&path)
+	}
+
+	/// Either every word has a count or none do
+	fn parse(name: &str, contents: &str) -> Result<Self, String> {
+		let mut words = vec![];
+		let mut counts = vec![];
+
+		for (i, line) in contents.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			match line.split_once('\t') {
+				Some((word, count)) => {
+					let count = count.trim().parse::<u64>()
+						.map_err(|_| format!("has an invalid count on line {}", i + 1))?;
+					words.push(word.trim().to_string());
+					counts.push(count);
+				}
+				None => words.push(line.to_string()),
+			}
+			if !counts.is_empty() && counts.len() != words.len() {
+				return Err(format!("has words with and without counts (line {})", i + 1));
+			}
+		}
+
+		if words.is_empty() {
+			return Err("doesn't contain any words".to_string());
+		}
+		if !counts.is_empty() && counts.iter().all(|count| *count == 0) {
+			return Err("only has counts of 0".to_string());
+		}
+
+		let counts = (!counts.is_empty()).then_some(counts);
+		Ok(Self { name: name.to_string(), words, counts })
+	}
+
+	/// Relative chance of each word being drawn, None if they're all the same
+	pub fn weights(&self, weighting: Weighting) -> Option<Vec<f64>> {
+		match (weighting, &self.counts) {
+			(Weighting::Uniform, _) => None,
+			(Weighting::Frequency, Some(counts)) =>
+				Some(counts.iter().map(|count| *count as f64).collect()),
+			(Weighting::Frequency | Weighting::Zipf, counts) => {
+				// lists without counts are taken to be sorted most common first
+				let mut ranked: Vec<usize> = (0..self.words.len()).collect();
+				if let Some(counts) = counts {
+					ranked.sort_by(|a, b| counts[*b].cmp(&counts[*a]));
+				}
+
+				let mut weights = vec![0.0; self.words.len()];
+				for (rank, i) in ranked.into_iter().enumerate() {
+					weights[i] = 1.0 / ((rank + 1) as f64).powf(ZIPF_EXPONENT);
+				}
+				Some(weights)
+			}
+		}
+	}
+}
+
+/// Names of every list `installed` can find, the bundled one first
+pub fn available() -> Vec<String> {
+	let mut installed: Vec<String> = languages_dir()
+		.and_then(|dir| fs::read_dir(dir).ok())
+		.into_iter()
+		.flatten()
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.path())
+		.filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+		.filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+		.filter(|name| name != DEFAULT_LANGUAGE && !name.starts_with('.'))
+		.collect();
+	installed.sort();
+
+	let mut names = vec![DEFAULT_LANGUAGE.to_string()];
+	names.append(&mut installed);
+	names
+}
+
+/// Where `--language` looks for word lists
+pub fn languages_dir() -> Option<PathBuf> {
+	paths::data_dir().map(|dir| dir.join(LANGUAGES_DIR))
+}
+
+#[cfg(test)]
+mod wordlist_tests {
+	use super::*;
+
+	#[test]
+	fn test_parse() {
+		let list = WordList::parse("test", "one\r\ntwo\n\n  three  \n").unwrap();
+		assert_eq!(list.words, vec!["one", "two", "three"]);
+		assert_eq!(list.counts, None);
+		assert!(WordList::parse("empty", "\n \n").is_err());
+
+		let list = WordList::parse("test", "the\t50\nof\t 30 \n\nzebra\t1\n").unwrap();
+		assert_eq!(list.words, vec!["the", "of", "zebra"]);
+		assert_eq!(list.counts, Some(vec![50, 30, 1]));
+		assert!(WordList::parse("test", "the\t50\nof\n").is_err());
+		assert!(WordList::parse("test", "the\nof\t30\n").is_err());
+		assert!(WordList::parse("test", "the\tlots\n").is_err());
+
+		assert_eq!(WordList::default().words.len(), ENGLISH_1K.lines().count());
+	}
+
+	#[test]
+	fn test_weights() {
+		let list = WordList::parse("test", "of\t30\nthe\t50\nzebra\t1\n").unwrap();
+		assert_eq!(list.weights(Weighting::Uniform), None);
+		assert_eq!(list.weights(Weighting::Frequency), Some(vec![30.0, 50.0, 1.0]));
+		assert_eq!(list.weights(Weighting::Zipf), Some(vec![0.5, 1.0, 1.0 / 3.0]));
+
+		let list = WordList::parse("test", "the\nof\n").unwrap();
+		assert_eq!(list.weights(Weighting::Frequency), Some(vec![1.0, 0.5]));
+	}
+
+	#[test]
+	fn test_bad_files() {
+		let missing = WordList::from_file(Path::new("/nonexistent/words.txt"));
+		assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+		let invalid = WordList::installed("../secrets");
+		assert_eq!(invalid.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+		assert_eq!(WordList::installed(DEFAULT_LANGUAGE).unwrap(), WordList::default());
+	}
+}